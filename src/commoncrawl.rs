@@ -23,19 +23,6 @@ pub enum WetRef<'a> {
     },
 }
 
-pub trait GetWetRef<'a> {
-    fn next_wet_ref(&'a self) -> (WetRef<'a>, &'a Self);
-}
-
-impl<'a> GetWetRef<'a> for [u8] {
-    fn next_wet_ref(&'a self) -> (WetRef<'a>, &'a [u8]) {
-        match parse_wet_ref(self) {
-            Result::Ok((rem, val)) => (val, rem),
-            Result::Err(err) => panic!("{:?}", err),
-        }
-    }
-}
-
 fn is_whitespace(chr: u8) -> bool {
     match char::from(chr) {
         ' ' | '\n' | '\r' | '\t' => true,
@@ -89,7 +76,7 @@ named!(parse_warcinfo<WetRef>, do_parse!(
     content_length: parse_content_length >>
     tag!("\r\n") >>
     content: take_str!(content_length) >>
-    take_while!(is_whitespace) >>
+    call!(nom::bytes::complete::take_while(is_whitespace)) >>
     (
         WetRef::WarcInfo {
             date,
@@ -113,7 +100,7 @@ named!(parse_conversion<WetRef>, do_parse!(
     content_length: parse_content_length >>
     tag!("\r\n") >>
     content: take_str!(content_length) >>
-    take_while!(is_whitespace) >>
+    call!(nom::bytes::complete::take_while(is_whitespace)) >>
     (
         WetRef::Conversion {
             url,
@@ -128,7 +115,7 @@ named!(parse_conversion<WetRef>, do_parse!(
 ));
 
 #[cfg_attr(rustfmt, rustfmt_skip)]
-named!(parse_wet_ref<WetRef>, do_parse!(
+named!(pub parse_wet_ref<WetRef>, do_parse!(
     pair!(tag!("WARC/1.0"), line_ending) >>
     result: alt!(parse_conversion | parse_warcinfo) >>
     (result)
@@ -0,0 +1,160 @@
+use std::fs::{copy, read_to_string, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use toml;
+use whatlang::Lang;
+
+use errors::StrError;
+use helpers::is_canonical;
+
+/// the file in a data dir that its settings are kept in
+const CONFIG_FILE: &str = "xray.toml";
+
+/// Settings of a data dir that the imports into it and the queries of it have to agree on. they
+/// are written to its `xray.toml` when it is created, later imports and queries read them from
+/// there, missing settings take their defaults
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// how many words, URLs or blobs there are between the entries of a store's jump table
+    pub jump_stride: u32,
+    pub analysis: Analysis,
+}
+
+/// How pages and queries are split into the words they are indexed and searched under
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Analysis {
+    /// the languages of the pages imported, as ISO 639-3 codes, other pages are skipped
+    pub languages: Vec<String>,
+    /// pages with fewer words than this are skipped
+    pub min_words: usize,
+    /// longer titles are left out, they are usually the page text rather than a title
+    pub max_title_len: usize,
+    /// the shortest and longest words indexed, in bytes
+    pub min_word_len: usize,
+    pub max_word_len: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            jump_stride: 1000,
+            analysis: Analysis::default(),
+        }
+    }
+}
+
+impl Default for Analysis {
+    fn default() -> Analysis {
+        Analysis {
+            languages: vec!["eng".to_string(), "spa".to_string(), "fra".to_string()],
+            min_words: 10,
+            max_title_len: 280,
+            min_word_len: 3,
+            max_word_len: 24,
+        }
+    }
+}
+
+impl Config {
+    /// reads the config of `data_dir`, the defaults if it doesn't have one
+    pub fn load(data_dir: &Path) -> Result<Config, StrError> {
+        Ok(Config::read(data_dir)?.unwrap_or_default())
+    }
+
+    /// reads the config of `data_dir`, None if it doesn't have one
+    pub fn read(data_dir: &Path) -> Result<Option<Config>, StrError> {
+        let path = data_dir.join(CONFIG_FILE);
+        let config = match read_to_string(&path) {
+            Ok(text) => toml::from_str::<Config>(&text)
+                .map_err(|err| format!("{} is invalid: {}", path.display(), err))?,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => Err(format!("couldn't read {}: {}", path.display(), err))?,
+        };
+
+        config
+            .check()
+            .map_err(|err| format!("{} is invalid: {}", path.display(), err))?;
+
+        Ok(Some(config))
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.jump_stride == 0 {
+            Err("jump_stride has to be at least 1")?;
+        }
+
+        let analysis = &self.analysis;
+        for code in &analysis.languages {
+            if Lang::from_code(code.as_str()).is_none() {
+                Err(format!("{} is not a language code", code))?;
+            }
+        }
+        // words are stored with a 1 byte length
+        if analysis.min_word_len == 0 || analysis.max_word_len > 255 {
+            Err("words have to be between 1 and 255 bytes long")?;
+        }
+        if analysis.min_word_len > analysis.max_word_len {
+            Err("min_word_len is larger than max_word_len")?;
+        }
+
+        Ok(())
+    }
+
+    /// writes the config to `data_dir` unless it has one already, so that the data dir keeps
+    /// these settings when the defaults change
+    pub fn persist(&self, data_dir: &Path) -> Result<(), StrError> {
+        let path = data_dir.join(CONFIG_FILE);
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == ErrorKind::AlreadyExists => return Ok(()),
+            Err(err) => Err(format!("couldn't create {}: {}", path.display(), err))?,
+        };
+
+        let text = toml::to_string(self).map_err(|err| err.to_string())?;
+        writeln!(file, "# the settings of this data dir, written when it was created. pages")?;
+        writeln!(file, "# imported before the analysis settings change are still indexed the")?;
+        writeln!(file, "# old way, which queries analyzed the new way can miss\n")?;
+        file.write_all(text.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// copies the config of the data dir `from` to the data dir `to` as it is, writing out the
+    /// defaults it uses instead if it has none
+    pub fn copy(from: &Path, to: &Path) -> Result<(), StrError> {
+        let path = from.join(CONFIG_FILE);
+        if path.exists() {
+            copy(&path, to.join(CONFIG_FILE))
+                .map_err(|err| format!("couldn't copy {}: {}", path.display(), err))?;
+            Ok(())
+        } else {
+            Config::default().persist(to)
+        }
+    }
+}
+
+impl Analysis {
+    /// lowercases a word and strips its punctuation, None for words that aren't indexed
+    pub fn canonicalize(&self, word: &str) -> Option<String> {
+        if word.len() >= self.min_word_len && word.len() <= self.max_word_len {
+            let mut output = String::with_capacity(word.len());
+            word.chars()
+                .filter(is_canonical)
+                .flat_map(|c| c.to_lowercase())
+                .for_each(|c| output.push(c));
+
+            if output.len() >= self.min_word_len {
+                return Some(output);
+            }
+        }
+        None
+    }
+
+    /// whether pages in `lang` are imported
+    pub fn allows_language(&self, lang: Lang) -> bool {
+        self.languages.iter().any(|code| code == lang.code())
+    }
+}
@@ -1,108 +1,691 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rayon::prelude::*;
 use rayon_hash::HashMap;
 use whatlang::Lang;
 
+use config::{Analysis, Config};
 use errors::StrError;
-use helpers::{add_pairs, canonicalize};
-use storage::Storage;
+use helpers::{add_pairs, fnv1a, is_near_duplicate, url_host, ReadableDuration};
+use lock::DataDirLock;
+use query::Query;
+use ranking::top_k;
+use snippet::Snippet;
+use storage::{Compaction, LoadedIndices, PageMeta, Storage};
 
+/// The index, split over one or more shards that each have a `Storage` of their own. page ids
+/// are interleaved across the shards, id `n` is page `n / shards` of shard `n % shards`, which
+/// for an unsharded data dir are just its ids.
+/// several data dirs can be opened together to search them as one, their shards are then all
+/// interleaved, which keeps the ids of pages from different dirs apart
 #[derive(Clone, Debug)]
 pub struct Database {
-    storage: Storage,
+    shards: Vec<Storage>,
+    data_dirs: Vec<String>,
+    /// the index into `data_dirs` of the dir each shard is in
+    shard_dirs: Vec<usize>,
+    /// held while pages added to this process haven't been flushed yet
+    lock: Option<Arc<DataDirLock>>,
+}
+
+#[allow(unused)]
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub id: u64,
+    pub score: usize,
+    pub url: String,
+    /// the data dir the page is in, only set when searching several
+    pub data_dir: Option<String>,
+    pub snippet: Option<Snippet>,
+    pub meta: Option<PageMeta>,
+    /// other URLs with exactly the same content
+    pub alternates: Vec<String>,
+}
+
+/// why `insert_page_url` didn't assign an id to a page
+pub enum Skipped {
+    Duplicate,
+    NearDuplicate,
+}
+
+/// what is hidden while paging through a ranked result set, and what has been shown so far
+#[derive(Clone, Debug, Default)]
+pub struct Diversity {
+    /// whether near duplicates of results already shown are hidden
+    pub collapse: bool,
+    /// the most results shown from any one host, 0 for no limit
+    pub per_host: usize,
+    /// the SimHashes of the results shown so far
+    seen: Vec<u64>,
+    /// the number of results shown so far from each host
+    hosts: HashMap<String, usize>,
+}
+
+impl Diversity {
+    pub fn new(collapse: bool, per_host: usize) -> Diversity {
+        Diversity {
+            collapse,
+            per_host,
+            ..Default::default()
+        }
+    }
+}
+
+/// the results left out of one page by `get_diverse_results`
+#[derive(Clone, Debug, Default)]
+pub struct HiddenResults {
+    pub near_duplicates: usize,
+    /// hosts that reached the per host limit, with how many of their results were left out
+    pub by_host: Vec<(String, usize)>,
+}
+
+impl HiddenResults {
+    pub fn add_host(&mut self, host: String, count: usize) {
+        match self.by_host.iter_mut().find(|entry| entry.0 == host) {
+            Some(entry) => entry.1 += count,
+            None => self.by_host.push((host, count)),
+        }
+    }
+
+    /// how many results were hidden, for any reason
+    pub fn total(&self) -> usize {
+        self.near_duplicates + self.by_host.iter().map(|entry| entry.1).sum::<usize>()
+    }
+
+    pub fn extend(&mut self, other: HiddenResults) {
+        self.near_duplicates += other.near_duplicates;
+        for (host, count) in other.by_host {
+            self.add_host(host, count);
+        }
+    }
 }
 
 pub struct Page {
     pub lang: Lang,
     pub title: Vec<String>,
     pub words: Vec<String>,
+    /// deflate-compressed page text, kept for snippets
+    pub text: Vec<u8>,
+    pub meta: PageMeta,
+}
+
+/// the sub-directories of a sharded data dir, `shard_0` up to the first one that doesn't exist
+pub fn shard_dirs(data_dir: &Path) -> Vec<PathBuf> {
+    (0..)
+        .map(|shard| data_dir.join(format!("shard_{}", shard)))
+        .take_while(|dir| dir.is_dir())
+        .collect()
+}
+
+/// turns the unique words of a query's fields into the words to look up in each tag, with the
+/// weight of a match in that tag
+pub fn query_fields(
+    query: &Query,
+    analysis: &Analysis,
+) -> Vec<(&'static str, Vec<String>, usize)> {
+    fn with_pairs(words: &[String], analysis: &Analysis) -> Vec<String> {
+        let mut words_with_pairs = words
+            .iter()
+            .filter_map(|word| analysis.canonicalize(word))
+            .collect::<Vec<_>>();
+
+        add_pairs(&mut words_with_pairs);
+
+        words_with_pairs
+    }
+
+    // unscoped words are searched for in both the title and the content
+    let words = with_pairs(&query.words, analysis);
+    let mut title_words = with_pairs(&query.title_words, analysis);
+    title_words.extend(words.iter().cloned());
+    let mut content_words = with_pairs(&query.body_words, analysis);
+    content_words.extend(words);
+    let url_words = with_pairs(&query.url_words, analysis);
+
+    vec![
+        ("by_title_word", title_words, 2),
+        ("by_word", content_words, 1),
+        ("by_url_word", url_words, 1),
+    ]
+}
+
+/// the `k` best pages of one shard, by their ids within the shard
+fn query_shard(
+    storage: &Storage,
+    query: &Query,
+    fields: &[(&'static str, Vec<String>, usize)],
+    k: usize,
+) -> Result<Vec<(u64, usize)>, StrError> {
+    let filter = match storage.get_filter(query) {
+        Some(filter) => filter,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut terms = Vec::new();
+    for &(tag, ref words, weight) in fields {
+        terms.extend(storage.get_terms(tag, words.clone(), weight)?);
+    }
+
+    Ok(top_k(terms, k, |id| filter.allows(id))?)
 }
 
 impl Database {
-    pub fn new(storage: Storage) -> Database {
-        Database { storage }
+    /// opens one or more data dirs, each of which is sharded if it has `shard_*` sub-directories.
+    /// only a single data dir can be written to, data dirs searched together have to analyze
+    /// pages the same way
+    pub fn open(data_dirs: &[String], load_indices: bool) -> Result<Database, StrError> {
+        let mut shards = Vec::new();
+        let mut dir_of_shard = Vec::new();
+        let mut first_config: Option<Config> = None;
+        for (idx, data_dir) in data_dirs.iter().enumerate() {
+            let data_dir = PathBuf::from(data_dir);
+            let config = Config::load(&data_dir)?;
+            match first_config {
+                Some(ref first) if first.analysis != config.analysis => Err(format!(
+                    "{} analyzes pages differently from {}, they can't be searched together",
+                    data_dir.display(),
+                    data_dirs[0]
+                ))?,
+                Some(_) => {}
+                None => first_config = Some(config.clone()),
+            }
+
+            let dirs = shard_dirs(&data_dir);
+            if dirs.is_empty() {
+                shards.push(Storage::new(data_dir, config, load_indices));
+                dir_of_shard.push(idx);
+            } else {
+                for dir in dirs {
+                    shards.push(Storage::new(dir, config.clone(), load_indices));
+                    dir_of_shard.push(idx);
+                }
+            }
+        }
+
+        Ok(Database {
+            shards,
+            data_dirs: data_dirs.to_vec(),
+            shard_dirs: dir_of_shard,
+            lock: None,
+        })
+    }
+
+    /// whether several data dirs were opened to be searched together, which can't be written to
+    pub fn is_federated(&self) -> bool {
+        self.data_dirs.len() > 1
+    }
+
+    /// the settings of the data dir, which all the data dirs opened share the analysis of
+    pub fn config(&self) -> &Config {
+        self.shards[0].config()
+    }
+
+    /// writes the settings in use to the data dir written to, if it has none yet, so that later
+    /// imports and queries keep using them
+    pub fn persist_config(&self) -> Result<(), StrError> {
+        self.config().persist(Path::new(&self.data_dirs[0]))
+    }
+
+    /// splits a new data dir into `count` shards, which imports are partitioned across by host
+    pub fn create_shards(data_dir: &str, count: usize) -> Result<(), StrError> {
+        use std::fs::{create_dir, metadata};
+
+        let data_dir = PathBuf::from(data_dir);
+        if count < 2 {
+            Err("a sharded data dir needs at least 2 shards")?;
+        }
+        // an import that started in the meantime would write to the unsharded data dir
+        let _lock = DataDirLock::acquire(&data_dir)?;
+        if !shard_dirs(&data_dir).is_empty() {
+            Err(format!("{} is already sharded", data_dir.display()))?;
+        }
+        let urls = data_dir.join("urls.xraystore");
+        if urls.exists() && metadata(&urls)?.len() > 0 {
+            Err(format!("{} already has pages imported into it", data_dir.display()))?;
+        }
+
+        for shard in 0..count {
+            create_dir(data_dir.join(format!("shard_{}", shard)))?;
+        }
+        Config::load(&data_dir)?.persist(&data_dir)?;
+        println!("split {} into {} shards", data_dir.display(), count);
+
+        Ok(())
+    }
+
+    /// merges the pages of the `sources` data dirs into the new data dir `into`, which is created
+    /// if it doesn't exist yet
+    pub fn merge(
+        into: &str,
+        sources: &[String],
+        chunk_size: usize,
+        buffer_size: usize,
+    ) -> Result<(), StrError> {
+        use std::fs::{canonicalize, create_dir_all};
+
+        create_dir_all(into)?;
+        let into_dir = canonicalize(into)?;
+        let _lock = DataDirLock::acquire(&into_dir)?;
+        if sources.is_empty() {
+            Err("there are no data dirs to merge")?;
+        }
+        if !shard_dirs(&into_dir).is_empty() {
+            Err(format!("{} is sharded, which merging doesn't support", into))?;
+        }
+        for source in sources {
+            if !shard_dirs(Path::new(source)).is_empty() {
+                Err(format!("{} is sharded, which merging doesn't support", source))?;
+            }
+            if canonicalize(source)? == into_dir {
+                Err(format!("can't merge {} into itself", source))?;
+            }
+        }
+
+        // the merged data dir keeps the settings of the ones merged into it
+        let config = Config::load(Path::new(&sources[0]))?;
+        for source in sources {
+            if Config::load(Path::new(source))? != config {
+                Err(format!("{} has different settings from {}", source, sources[0]))?;
+            }
+        }
+        if Config::read(&into_dir)?.is_some_and(|into_config| into_config != config) {
+            Err(format!("{} has different settings from {}", into, sources[0]))?;
+        }
+        config.persist(&into_dir)?;
+
+        let sources = sources
+            .iter()
+            .map(|source| Storage::new(source.as_str(), config.clone(), true))
+            .collect::<Vec<_>>();
+        let mut storage = Storage::new(into_dir, config, true);
+        storage.merge_from(&sources, chunk_size, buffer_size)?;
+        println!("merged {} data dirs into {}", sources.len(), into);
+
+        Ok(())
+    }
+
+    /// the storage of each shard
+    pub fn shards(&self) -> &[Storage] {
+        &self.shards
+    }
+
+    pub fn shards_mut(&mut self) -> &mut [Storage] {
+        &mut self.shards
+    }
+
+    /// takes the lock on the data dir for this process, if it doesn't hold it already. returns
+    /// whether it was taken now
+    pub fn lock(&mut self) -> Result<bool, StrError> {
+        if self.lock.is_some() {
+            return Ok(false);
+        }
+
+        let lock = DataDirLock::acquire(Path::new(&self.data_dirs[0]))?;
+        self.lock = Some(Arc::new(lock));
+        Ok(true)
+    }
+
+    /// lets other processes write to the data dir again
+    pub fn unlock(&mut self) {
+        self.lock = None;
+    }
+
+    /// loads the indices of every shard another process has changed since they were loaded,
+    /// leaving the ones in use as they are, so that searches can go on in the meantime
+    pub fn load_changed(&self) -> Result<Vec<(usize, LoadedIndices)>, StrError> {
+        let mut loaded = Vec::new();
+        for (shard, storage) in self.shards.iter().enumerate() {
+            if storage.is_stale() {
+                loaded.push((shard, storage.load_indices()?));
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// swaps in the indices from `load_changed`, returns how many shards were reloaded
+    pub fn swap_indices(&mut self, loaded: Vec<(usize, LoadedIndices)>) -> usize {
+        let mut swapped = 0;
+        for (shard, indices) in loaded {
+            if self.shards[shard].swap_indices(indices) {
+                swapped += 1;
+            }
+        }
+        swapped
+    }
+
+    /// reloads the indices of every shard another process has changed, returns how many
+    pub fn reload(&mut self) -> Result<usize, StrError> {
+        let loaded = self.load_changed()?;
+        Ok(self.swap_indices(loaded))
+    }
+
+    /// the id of a page across all shards, from the shard it is on and its id within the shard
+    fn global_id(&self, shard: usize, id: u64) -> u64 {
+        id * self.shards.len() as u64 + shard as u64
+    }
+
+    /// the shard a page is on and its id within the shard
+    pub fn split_id(&self, id: u64) -> (usize, u64) {
+        let num_shards = self.shards.len() as u64;
+        ((id % num_shards) as usize, id / num_shards)
+    }
+
+    /// the shard the pages of a URL's host go to
+    fn shard_of(&self, url: &str) -> usize {
+        let host = url_host(url).unwrap_or_default();
+        (fnv1a(host.as_bytes()) % self.shards.len() as u64) as usize
+    }
+
+    /// runs `get` on each shard with the ids of the given pages that are on it, keying what it
+    /// returns by the pages' global ids
+    fn get_per_shard<T, F>(&self, ids: &[u64], get: F) -> HashMap<u64, T>
+    where
+        F: Fn(&Storage, Vec<u64>) -> HashMap<u64, T>,
+    {
+        let mut local_ids = vec![Vec::new(); self.shards.len()];
+        for &id in ids {
+            let (shard, id) = self.split_id(id);
+            local_ids[shard].push(id);
+        }
+
+        let mut results = HashMap::new();
+        for (shard, ids) in local_ids.into_iter().enumerate() {
+            if ids.is_empty() {
+                continue;
+            }
+            for (id, value) in get(&self.shards[shard], ids) {
+                results.insert(self.global_id(shard, id), value);
+            }
+        }
+
+        results
     }
 
     pub fn insert_url(&mut self, url: String) -> u64 {
-        self.storage.insert_url(url)
+        let shard = self.shard_of(&url);
+        let storage = &mut self.shards[shard];
+        let id = storage.insert_url(url.clone());
+        storage.index_url(id, &url);
+        self.global_id(shard, id)
+    }
+
+    /// assigns a URL id to a page, unless a page with the same block digest was already imported,
+    /// in which case the URL is only recorded as an alternate of that page, or near duplicates
+    /// are being skipped and one was already imported. pages are compared with those on every
+    /// shard, as copies of a page are usually on other hosts
+    pub fn insert_page_url(&mut self, url: String, meta: &PageMeta) -> Result<u64, Skipped> {
+        if !meta.block_digest.is_empty() {
+            let canonical = self.shards.iter().enumerate().find_map(|(shard, storage)| {
+                storage
+                    .find_digest(&meta.block_digest)
+                    .map(|canonical_id| (shard, canonical_id))
+            });
+            // the alternate URL is kept on the shard of the page it is a copy of, which is where
+            // its alternates are looked up
+            if let Some((shard, canonical_id)) = canonical {
+                let storage = &mut self.shards[shard];
+                let id = storage.insert_url(url);
+                storage.insert_alternate(canonical_id, id);
+                return Err(Skipped::Duplicate);
+            }
+        }
+
+        if self.shards
+            .iter()
+            .any(|storage| storage.find_near_duplicate(meta.simhash).is_some())
+        {
+            return Err(Skipped::NearDuplicate);
+        }
+
+        let id = self.insert_url(url);
+        let (shard, local_id) = self.split_id(id);
+        let storage = &mut self.shards[shard];
+        if !meta.block_digest.is_empty() {
+            storage.insert_digest(local_id, &meta.block_digest);
+        }
+        storage.insert_simhash(local_id, meta.simhash);
+
+        Ok(id)
     }
 
     pub fn insert(&mut self, url: u64, page: Page) {
-        let Page { title, words, lang } = page;
+        let Page {
+            title,
+            words,
+            lang,
+            text,
+            meta,
+        } = page;
 
-        self.storage.insert_lang(url, lang);
+        let (shard, url) = self.split_id(url);
+        let storage = &mut self.shards[shard];
+
+        storage.insert_lang(url, lang);
+        storage.insert_date(url, &meta.date);
+        storage.insert_doc(url, text);
+        storage.insert_meta(url, &meta);
 
         for title_word in title {
-            self.storage.insert_word(url, true, title_word);
+            storage.insert_word(url, true, title_word);
         }
 
         for word in words {
-            self.storage.insert_word(url, false, word);
+            storage.insert_word(url, false, word);
+        }
+    }
+
+    pub fn load_digests(&mut self) -> Result<(), StrError> {
+        for storage in &mut self.shards {
+            storage.load_digests()?;
+        }
+        Ok(())
+    }
+
+    /// makes `insert_page_url` skip pages that are near duplicates of an imported page
+    pub fn load_simhash_buckets(&mut self) -> Result<(), StrError> {
+        for storage in &mut self.shards {
+            storage.load_simhash_buckets()?;
         }
+        Ok(())
     }
 
     pub fn persist_urls(&mut self) {
-        self.storage.persist_urls();
+        self.shards.par_iter_mut().for_each(|storage| storage.persist_urls());
     }
 
     pub fn persist(&mut self, unique: Option<u64>) {
-        self.storage.persist(unique);
+        self.shards
+            .par_iter_mut()
+            .for_each(|storage| storage.persist(unique));
+    }
+
+    pub fn optimize(&mut self, chunk_size: usize, buffer_size: usize) -> Result<(), StrError> {
+        for storage in &mut self.shards {
+            storage.optimize(chunk_size, buffer_size)?;
+        }
+        Ok(())
     }
 
-    pub fn optimize(&mut self, chunk_size: usize) -> Result<(), StrError> {
-        self.storage.optimize(chunk_size)
+    pub fn compact(&mut self, fanout: usize, buffer_size: usize) -> Result<(), StrError> {
+        let merges = self.compact_with(fanout, buffer_size, &mut |compaction| {
+            println!(
+                "compacted {} {} stores in {}",
+                compaction.stores,
+                compaction.tag,
+                compaction.duration.readable()
+            );
+        })?;
+        println!("performed {} compactions", merges);
+
+        Ok(())
+    }
+
+    /// compacts every shard, passing each merge to `report`. returns the number of merges
+    pub fn compact_with(
+        &mut self,
+        fanout: usize,
+        buffer_size: usize,
+        report: &mut dyn FnMut(Compaction),
+    ) -> Result<usize, StrError> {
+        let mut merges = 0;
+        for storage in &mut self.shards {
+            merges += storage.compact(fanout, buffer_size, report)?;
+        }
+
+        Ok(merges)
     }
 
     pub fn rebuild_index(&mut self) -> Result<(), StrError> {
-        self.storage.rebuild_index()
+        for storage in &mut self.shards {
+            storage.rebuild_index()?;
+        }
+        Ok(())
     }
 
+    /// the number after the highest numbered store on any shard
     pub fn num_stores(&mut self) -> usize {
-        self.storage.num_indexed_stores() / 3
+        self.shards
+            .iter()
+            .map(|storage| storage.num_indexed_chunks())
+            .max()
+            .unwrap_or(0)
     }
 
-    pub fn query(&mut self, words: Vec<String>, lang: Option<Lang>) {
-        let mut words_with_pairs = words
-            .into_iter()
-            .filter_map(|word| canonicalize(&word))
+    /// the languages that have at least one page in the index
+    pub fn langs(&self) -> Vec<Lang> {
+        let mut langs = self.shards
+            .iter()
+            .flat_map(|storage| storage.langs())
             .collect::<Vec<_>>();
+        langs.sort_unstable_by(|a, b| a.code().cmp(b.code()));
+        langs.dedup();
+        langs
+    }
 
-        add_pairs(&mut words_with_pairs);
+    /// performs a query, restricted by its language, date range and sites if any are given
+    /// returns the `k` best matching (url id, score) pairs, best first with ties broken by url id
+    /// so that the ordering is stable across pages, fewer when not that many pages match.
+    /// each shard is queried for its own `k` best in parallel, and those are merged
+    pub fn query(&self, query: &Query, k: usize) -> Result<Vec<(u64, usize)>, StrError> {
+        let fields = query_fields(query, &self.config().analysis);
 
-        let (title_sets, content_sets) = self.storage
-            .get_word_sets(lang.unwrap_or(Lang::Eng), words_with_pairs);
+        let per_shard = self.shards
+            .par_iter()
+            .map(|storage| query_shard(storage, query, &fields, k))
+            .collect::<Vec<_>>();
 
-        if title_sets.is_empty() && content_sets.is_empty() {
-            println!("no matches found");
-            return;
+        let mut results = Vec::new();
+        for (shard, ranked) in per_shard.into_iter().enumerate() {
+            results.extend(
+                ranked?
+                    .into_iter()
+                    .map(|(id, score)| (self.global_id(shard, id), score)),
+            );
         }
 
-        let mut results = HashMap::new();
+        results.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        results.truncate(k);
 
-        for word in title_sets {
-            for url in word.1 {
-                *results.entry(url).or_insert(0) += word.0.len() * 2;
-            }
-        }
+        Ok(results)
+    }
 
-        for word in content_sets {
-            for url in word.1 {
-                *results.entry(url).or_insert(0) += word.0.len();
-            }
-        }
+    /// fetches the URLs, metadata, snippets and alternate URLs for one page of a ranked result set, with `terms`
+    /// being the query words to highlight
+    pub fn get_results(
+        &self,
+        ranked: &[(u64, usize)],
+        terms: &[String],
+        offset: usize,
+        limit: usize,
+    ) -> Vec<SearchResult> {
+        let page = ranked
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
 
-        let mut results = results.into_iter().collect::<Vec<_>>();
-        results.sort_by_key(|r| r.1);
-        results.reverse();
+        let federated = self.is_federated();
+        let ids = page.iter().map(|r| r.0).collect::<Vec<_>>();
+        let mut urls = self.get_per_shard(&ids, Storage::get_urls);
+        let docs = self.get_per_shard(&ids, Storage::get_docs);
+        let mut meta = self.get_per_shard(&ids, Storage::get_meta);
+        let mut alternates = self.get_per_shard(&ids, Storage::get_alternates);
 
-        let len = results.len();
+        page.into_iter()
+            .map(|(id, score)| {
+                let url = urls.remove(&id).unwrap();
 
-        let results: Vec<u64> = results.into_iter().take(10).map(|r| r.0).collect();
+                // importing the same crawl twice records the canonical URL as its own alternate
+                let mut alternates = alternates.remove(&id).unwrap_or_default();
+                alternates.retain(|alternate| alternate != &url);
+                alternates.dedup();
 
-        let result_map = self.storage.get_urls(results.clone());
+                let data_dir = if federated {
+                    let (shard, _) = self.split_id(id);
+                    Some(self.data_dirs[self.shard_dirs[shard]].clone())
+                } else {
+                    None
+                };
 
-        println!("{} results", len);
+                SearchResult {
+                    id,
+                    score,
+                    url,
+                    data_dir,
+                    snippet: docs
+                        .get(&id)
+                        .map(|text| Snippet::new(text, terms, &self.config().analysis)),
+                    meta: meta.remove(&id),
+                    alternates,
+                }
+            })
+            .collect()
+    }
 
-        results
-            .iter()
-            .for_each(|url_id| println!("{}", result_map[url_id]));
+    /// like `get_results`, but leaves out the pages `diversity` hides and records the ones returned
+    /// in it. `offset` is advanced past every result considered, including the ones left out
+    pub fn get_diverse_results(
+        &self,
+        ranked: &[(u64, usize)],
+        terms: &[String],
+        offset: &mut usize,
+        limit: usize,
+        diversity: &mut Diversity,
+    ) -> (Vec<SearchResult>, HiddenResults) {
+        let mut results = Vec::new();
+        let mut hidden = HiddenResults::default();
+
+        while results.len() < limit && *offset < ranked.len() {
+            let batch = self.get_results(ranked, terms, *offset, limit - results.len());
+            *offset += batch.len();
+
+            for result in batch {
+                // a SimHash of 0 means it is unknown
+                let simhash = result.meta.as_ref().map_or(0, |meta| meta.simhash);
+                if diversity.collapse && simhash != 0
+                    && diversity.seen.iter().any(|&other| is_near_duplicate(other, simhash))
+                {
+                    hidden.near_duplicates += 1;
+                    continue;
+                }
+
+                let host = url_host(&result.url).unwrap_or_default();
+                let shown = diversity.hosts.entry(host.clone()).or_insert(0);
+                if diversity.per_host > 0 && *shown >= diversity.per_host {
+                    hidden.add_host(host, 1);
+                    continue;
+                }
+                *shown += 1;
+
+                if simhash != 0 {
+                    diversity.seen.push(simhash);
+                }
+                results.push(result);
+            }
+        }
+
+        (results, hidden)
     }
 }
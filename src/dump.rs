@@ -0,0 +1,140 @@
+use std::fs::canonicalize;
+use std::io::{stdout, BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use structopt::StructOpt;
+
+use database::Database;
+use errors::StrError;
+
+/// how `dump` writes out records
+#[derive(Clone, Copy, Debug)]
+pub enum DumpFormat {
+    JsonLines,
+    Tsv,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<DumpFormat, String> {
+        match format {
+            "jsonl" => Ok(DumpFormat::JsonLines),
+            "tsv" => Ok(DumpFormat::Tsv),
+            _ => Err(format!("unknown dump format {}, expected jsonl or tsv", format)),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct DumpOptions {
+    #[structopt(long = "tag")]
+    /// Dumps every indexed store with this tag, like by_word or by_host
+    pub tag: Option<String>,
+
+    #[structopt(long = "file")]
+    /// Dumps the indexed or URL store in this file of the data directory
+    pub file: Option<String>,
+
+    #[structopt(long = "urls")]
+    /// Dumps the URL stores as (id, URL) pairs
+    pub urls: bool,
+
+    #[structopt(long = "format", default_value = "jsonl")]
+    /// How to write out the records, jsonl for JSON lines or tsv
+    pub format: DumpFormat,
+
+    #[structopt(long = "postings")]
+    /// Writes out the ids in each word's set rather than only how many there are
+    pub postings: bool,
+
+    #[structopt(long = "jump-table")]
+    /// Dumps the jump tables of the stores instead of their contents
+    pub jump_table: bool,
+
+    #[structopt(long = "prefix")]
+    /// Only dumps the words, or URLs, that start with this
+    pub prefix: Option<String>,
+
+    #[structopt(long = "min-id")]
+    /// Only dumps ids from this one on, words are left out if none of their ids are in range
+    pub min_id: Option<u64>,
+
+    #[structopt(long = "max-id")]
+    /// Only dumps ids up to and including this one
+    pub max_id: Option<u64>,
+}
+
+impl DumpOptions {
+    /// whether only some ids are dumped
+    pub fn has_id_range(&self) -> bool {
+        self.min_id.is_some() || self.max_id.is_some()
+    }
+
+    /// the first and last id that are dumped
+    pub fn id_range(&self) -> (u64, u64) {
+        (self.min_id.unwrap_or(0), self.max_id.unwrap_or(u64::MAX))
+    }
+}
+
+impl Database {
+    /// writes out the contents of the stores chosen by the options to stdout. ids are the ones
+    /// stored, which in a sharded data dir are the ids within the store's shard
+    pub fn dump(&self, data_dir: &str, options: &DumpOptions) -> Result<(), StrError> {
+        let chosen = options.tag.is_some() as usize + options.file.is_some() as usize +
+            options.urls as usize;
+        if chosen != 1 {
+            Err("choose what to dump with exactly one of --tag, --file and --urls")?;
+        }
+
+        let root = canonicalize(data_dir)?;
+        let file = match options.file {
+            Some(ref file) => {
+                let file = canonicalize(file).map_err(|err| format!("{}: {}", file, err))?;
+                let shard = self.shards()
+                    .iter()
+                    .position(|storage| storage.has_store(&file))
+                    .ok_or_else(|| format!("{} isn't a store of the data dir", file.display()))?;
+                Some((shard, file))
+            }
+            None => None,
+        };
+
+        let stdout = stdout();
+        let mut out = BufWriter::new(stdout.lock());
+        let result = self.dump_stores(&root, file, options, &mut out)
+            .and_then(|()| out.flush());
+
+        match result {
+            // the reader went away, like `head` having read enough
+            Err(ref err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+            result => Ok(result?),
+        }
+    }
+
+    /// dumps the store `file` of the shard it is on if it is given, otherwise every store that
+    /// the options choose
+    fn dump_stores(
+        &self,
+        root: &Path,
+        file: Option<(usize, PathBuf)>,
+        options: &DumpOptions,
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        if let Some((shard, file)) = file {
+            let storage = &self.shards()[shard];
+            storage.dump_indexed(None, Some(&file), root, options, out)?;
+            return storage.dump_urls(Some(&file), root, options, out);
+        }
+
+        for storage in self.shards() {
+            match options.tag {
+                Some(ref tag) => storage.dump_indexed(Some(tag), None, root, options, out)?,
+                None => storage.dump_urls(None, root, options, out)?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use serde_json::Value;
+use whatlang::Lang;
+
+use database::{query_fields, Database};
+use errors::StrError;
+use query::Query;
+
+/// How one word of a query scored for a page
+pub struct TermExplanation {
+    /// the part of the page the word was looked up in: title, body or url
+    pub field: &'static str,
+    /// the canonical word, or pair of words joined by `|`
+    pub word: String,
+    /// the number of pages in the page's shard that have the word there
+    pub postings: u64,
+    pub matched: bool,
+    /// what matching the word adds to a page's score
+    pub score: usize,
+}
+
+/// Why a page scored what it did for a query, scored the way `Database::query` does
+pub struct Explanation {
+    /// the sum of the scores of the words matched
+    pub score: usize,
+    pub terms: Vec<TermExplanation>,
+    /// the language the query was restricted to, None when every language was searched
+    pub query_lang: Option<Lang>,
+    pub page_lang: Option<Lang>,
+    /// whether the query's language, date range and site operators let the page through
+    pub allowed: bool,
+}
+
+/// the name a tag searched by queries is shown under
+fn field_name(tag: &str) -> &'static str {
+    match tag {
+        "by_title_word" => "title",
+        "by_url_word" => "url",
+        _ => "body",
+    }
+}
+
+impl Explanation {
+    /// the lines printed under a result by `search --explain`
+    pub fn lines(&self) -> Vec<String> {
+        let lang = |lang: Option<Lang>| {
+            lang.map_or_else(|| "all".to_string(), |lang| lang.code().to_string())
+        };
+        let mut lines = vec![format!(
+            "explain: score {}, language {} (query {}), filters {}",
+            self.score,
+            lang(self.page_lang),
+            lang(self.query_lang),
+            if self.allowed { "pass" } else { "fail" }
+        )];
+
+        for term in &self.terms {
+            let score = if term.matched {
+                format!("+{}", term.score)
+            } else {
+                "-".to_string()
+            };
+            lines.push(format!(
+                "  {:>5} {}:{} ({} postings)",
+                score, term.field, term.word, term.postings
+            ));
+        }
+
+        lines
+    }
+
+    pub fn to_json(&self) -> Value {
+        let terms = self.terms
+            .iter()
+            .map(|term| {
+                json!({
+                    "field": term.field,
+                    "word": term.word,
+                    "postings": term.postings,
+                    "matched": term.matched,
+                    "score": term.score,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!({
+            "score": self.score,
+            "query_lang": self.query_lang.map(|lang| lang.code().to_string()),
+            "page_lang": self.page_lang.map(|lang| lang.code().to_string()),
+            "allowed": self.allowed,
+            "terms": terms,
+        })
+    }
+}
+
+impl Database {
+    /// scores the page `id` for the query word by word, listing the words of every field the
+    /// query looks in, whether the page matched them and what they added to its score
+    pub fn explain(&self, query: &Query, id: u64) -> Result<Explanation, StrError> {
+        let (shard, local_id) = self.split_id(id);
+        let storage = &self.shards()[shard];
+
+        let mut terms = Vec::new();
+        for (tag, words, weight) in query_fields(query, &self.config().analysis) {
+            let mut cursors = storage.get_terms(tag, words.clone(), weight)?;
+            let mut words = words;
+            words.sort_unstable();
+            words.dedup();
+
+            for word in words {
+                // words that no page has don't get a cursor
+                let term = match cursors.iter().position(|cursor| cursor.word == word) {
+                    Some(idx) => {
+                        let mut cursor = cursors.swap_remove(idx);
+                        TermExplanation {
+                            field: field_name(tag),
+                            word,
+                            postings: cursor.num_postings(),
+                            matched: cursor.seek(local_id)? == Some(local_id),
+                            score: cursor.score,
+                        }
+                    }
+                    None => TermExplanation {
+                        field: field_name(tag),
+                        word,
+                        postings: 0,
+                        matched: false,
+                        score: 0,
+                    },
+                };
+                terms.push(term);
+            }
+        }
+
+        let page_lang = storage
+            .get_meta(vec![local_id])
+            .remove(&local_id)
+            .map(|meta| meta.lang);
+        let allowed = storage
+            .get_filter(query)
+            .is_some_and(|filter| filter.allows(local_id));
+
+        Ok(Explanation {
+            score: terms.iter().filter(|term| term.matched).map(|term| term.score).sum(),
+            terms,
+            query_lang: query.lang,
+            page_lang,
+            allowed,
+        })
+    }
+}
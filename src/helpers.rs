@@ -1,5 +1,12 @@
+use std::io::{Read, Write};
 use std::time::Duration;
 
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+use config::Analysis;
+
 pub trait ReadableDuration {
     fn readable(&self) -> String;
 }
@@ -26,21 +33,6 @@ pub fn is_canonical(x: &char) -> bool {
           x == '"' || x == ':' || x == ';' || x == '@' || x == '|')
 }
 
-pub fn canonicalize(word: &str) -> Option<String> {
-    if word.len() > 2 && word.len() < 25 {
-        let mut output = String::with_capacity(word.len());
-        word.chars()
-            .filter(is_canonical)
-            .flat_map(|c| c.to_lowercase())
-            .for_each(|c| output.push(c));
-
-        if output.len() > 2 {
-            return Some(output);
-        }
-    }
-    None
-}
-
 pub fn add_pairs(words: &mut Vec<String>) {
     if words.is_empty() {
         return;
@@ -58,3 +50,148 @@ pub fn add_pairs(words: &mut Vec<String>) {
 
     words.extend(word_pairs);
 }
+
+pub fn compress(text: &str) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+pub fn decompress(data: &[u8]) -> Option<String> {
+    let mut text = String::new();
+    DeflateDecoder::new(data).read_to_string(&mut text).ok()?;
+    Some(text)
+}
+
+/// extracts the lowercased host of a URL, without any port or credentials
+pub fn url_host(url: &str) -> Option<String> {
+    let rest = match url.find("://") {
+        Some(idx) => &url[idx + 3..],
+        None => url,
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?.split(':').next()?;
+
+    if host.is_empty() || host.len() > 255 {
+        None
+    } else {
+        Some(host.to_lowercase())
+    }
+}
+
+/// guesses the registered domain of a host, such as `example.com` for `blog.example.com`
+///
+/// without a public suffix list this treats a short second level label under a two letter
+/// country code (like `co.uk` or `com.au`) as part of the suffix
+pub fn registered_domain(host: &str) -> &str {
+    let labels = host.rsplit('.').collect::<Vec<_>>();
+    if labels.len() < 2 || labels.iter().all(|label| label.chars().all(|c| c.is_ascii_digit())) {
+        return host;
+    }
+
+    let suffix_labels = if labels[0].len() == 2 && labels.len() > 2 &&
+        ["ac", "co", "com", "edu", "gov", "net", "org"].contains(&labels[1])
+    {
+        2
+    } else {
+        1
+    };
+
+    let keep = (suffix_labels + 1).min(labels.len());
+    let start = labels[..keep].iter().map(|label| label.len() + 1).sum::<usize>() - 1;
+    &host[host.len() - start..]
+}
+
+/// splits the host, path and query of a URL into canonical words, leaving out the scheme
+pub fn url_words(url: &str, analysis: &Analysis) -> Vec<String> {
+    let rest = match url.find("://") {
+        Some(idx) => &url[idx + 3..],
+        None => url,
+    };
+
+    let mut words = rest.split(|c: char| !c.is_alphanumeric())
+        .filter_map(|word| analysis.canonicalize(word))
+        .collect::<Vec<_>>();
+
+    words.sort_unstable();
+    words.dedup();
+
+    words
+}
+
+/// near duplicate pages have SimHashes that differ in at most this many bits
+pub const NEAR_DUPLICATE_DISTANCE: u32 = 3;
+
+/// the number of 16 bit bands a SimHash is split into for locality sensitive hashing, two
+/// hashes within `NEAR_DUPLICATE_DISTANCE` bits of each other always share at least one band
+pub const SIMHASH_BANDS: u64 = 4;
+
+/// 64 bit FNV-1a, used because its output is stable across builds and platforms
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// computes the 64 bit SimHash of a page from its words
+pub fn simhash(words: &[String]) -> u64 {
+    let mut counts = [0i64; 64];
+    for word in words {
+        let hash = fnv1a(word.as_bytes());
+        for (bit, count) in counts.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+
+    counts
+        .iter()
+        .enumerate()
+        .filter(|&(_, &count)| count > 0)
+        .fold(0, |hash, (bit, _)| hash | (1 << bit))
+}
+
+pub fn is_near_duplicate(a: u64, b: u64) -> bool {
+    (a ^ b).count_ones() <= NEAR_DUPLICATE_DISTANCE
+}
+
+/// the LSH bucket keys of a SimHash, one per band
+pub fn simhash_bands(hash: u64) -> Vec<String> {
+    (0..SIMHASH_BANDS)
+        .map(|band| format!("{}:{:04x}", band, (hash >> (band * 16)) & 0xffff))
+        .collect()
+}
+
+/// the score a page gets for matching a query word, before the weight of the field it matched in.
+/// it is also the max score ranking prunes by, which only holds while it depends on the word alone
+pub fn term_score(word: &str) -> usize {
+    word.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_domains() {
+        assert_eq!(registered_domain("www.example.com"), "example.com");
+        assert_eq!(registered_domain("example.com"), "example.com");
+        assert_eq!(registered_domain("a.b.c.example.org"), "example.org");
+        // a short second level label under a country code is part of the suffix
+        assert_eq!(registered_domain("news.bbc.co.uk"), "bbc.co.uk");
+        assert_eq!(registered_domain("bbc.co.uk"), "bbc.co.uk");
+        assert_eq!(registered_domain("shop.example.com.au"), "example.com.au");
+        assert_eq!(registered_domain("es.wikipedia.org"), "wikipedia.org");
+        assert_eq!(registered_domain("www.example.de"), "example.de");
+        // without a label in front of it, the suffix is all there is
+        assert_eq!(registered_domain("co.uk"), "co.uk");
+        assert_eq!(registered_domain("localhost"), "localhost");
+        assert_eq!(registered_domain("192.168.0.1"), "192.168.0.1");
+    }
+}
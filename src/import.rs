@@ -1,197 +1,313 @@
 use helpers::add_pairs;
-use helpers::canonicalize;
-use std::fs::File;
-use std::fs::read_dir;
-use std::io::{BufReader, Read};
-use std::path::PathBuf;
+use helpers::compress;
+use helpers::simhash;
+use std::path::Path;
 use std::time::Instant;
 
-use flate2::read::MultiGzDecoder;
 use rayon::prelude::*;
-use whatlang::{detect, Lang};
+use whatlang::detect;
 
-use commoncrawl::{GetWetRef, WetRef};
+use config::Analysis;
 use database::Database;
-use database::Page;
+use database::{Page, Skipped};
 use errors::StrError;
 use helpers::ReadableDuration;
+use progress::{source_size, ImportCounts, LogFormat, Progress};
+use sources::compression::read_content;
+use sources::{path_to_files, Format, RawPage, Source};
+use storage::{PageMeta, DEFAULT_BUFFER_SIZE, DEFAULT_FANOUT};
+
+/// the most page text kept for snippets, longer pages are truncated
+const MAX_TEXT_LEN: usize = 16 * 1024;
+
+/// why `analyze_page` didn't make a page out of a record
+#[derive(Clone, Copy, Debug)]
+pub enum Dropped {
+    /// not in one of the languages imported, or in no language that could be detected
+    Language,
+    TooShort,
+}
 
-fn load_source(source: PathBuf) -> Result<Vec<(String, Page)>, StrError> {
-    let mut raw_pages = Vec::new();
+/// splits a page into the words it is indexed under and the text and metadata stored for it
+pub fn analyze_page(raw_page: RawPage, analysis: &Analysis) -> Result<(String, Page), Dropped> {
+    let RawPage {
+        url,
+        title,
+        text,
+        date,
+        record_id,
+        refers_to,
+        block_digest,
+    } = raw_page;
+
+    let lang = detect(&text).ok_or(Dropped::Language)?.lang();
+    if !analysis.allows_language(lang) {
+        return Err(Dropped::Language);
+    }
+    let content_length = text.len() as u64;
+    // pages without a title of their own have it on their first line
+    let (mut title, content) = match title {
+        Some(ref title) => (title.as_str(), text.as_str()),
+        None => text.split_at(text.find('\n').unwrap_or(0)),
+    };
+
+    if title.len() > analysis.max_title_len {
+        title = ""; // title is invalid
+    }
 
-    // shorten peak memory usage time by deallocating `content` after this block
-    {
-        let is_gzip = source.to_str().unwrap().ends_with(".gz");
+    let mut meta = PageMeta {
+        title: title.trim().to_string(),
+        date,
+        record_id,
+        refers_to,
+        block_digest,
+        content_length,
+        lang,
+        simhash: 0,
+    };
+
+    let mut title = title
+        .split_whitespace()
+        .filter_map(|word| analysis.canonicalize(word))
+        .collect::<Vec<_>>();
 
-        let mut file = File::open(&source)?;
-        let content = &mut Vec::new();
+    add_pairs(&mut title);
 
-        if is_gzip {
-            if let Err(_) = MultiGzDecoder::new(BufReader::new(file)).read_to_end(content) {
-                return Err(format!(
-                    "decoding gzip stream failed for {}",
-                    source.display()
-                ))?;
-            }
-        } else {
-            file.read_to_end(content)?;
-        }
+    title.sort_unstable();
+    title.dedup();
+    title.shrink_to_fit();
 
-        content.shrink_to_fit();
+    let mut words = content
+        .split_whitespace()
+        .filter_map(|word| analysis.canonicalize(word))
+        .collect::<Vec<_>>();
 
-        let mut remaining: &[u8] = content;
-        while !remaining.is_empty() {
-            let (blob, rem) = remaining.next_wet_ref();
-            remaining = rem;
-            if let WetRef::Conversion { url, content, .. } = blob {
-                raw_pages.push((url.to_string(), content.to_string()))
-            }
-        }
+    if words.len() < analysis.min_words {
+        return Err(Dropped::TooShort);
     }
 
-    raw_pages.shrink_to_fit();
+    add_pairs(&mut words);
 
-    let mut pages = raw_pages
-        .into_par_iter()
-        .filter_map(|(url, content)| {
-            let lang = detect(&content)?.lang();
-            let title_end = content.find('\n').unwrap_or(0);
-            let (mut title, content) = content.split_at(title_end);
+    words.sort_unstable();
+    words.dedup();
+    words.shrink_to_fit();
 
-            if title.len() > 280 {
-                title = ""; // title is invalid
-            }
+    meta.simhash = simhash(&words);
 
-            let mut title = title
-                .split_whitespace()
-                .filter_map(canonicalize)
-                .collect::<Vec<_>>();
+    let mut text_end = content.len().min(MAX_TEXT_LEN);
+    while !content.is_char_boundary(text_end) {
+        text_end -= 1;
+    }
+    let text = compress(content[..text_end].trim());
+
+    Ok((
+        url,
+        Page {
+            lang,
+            title,
+            words,
+            text,
+            meta,
+        },
+    ))
+}
 
-            add_pairs(&mut title);
+/// The pages of a source, and what was read to get them
+struct LoadedSource {
+    pages: Vec<(String, Page)>,
+    counts: ImportCounts,
+    /// the error of the first record that couldn't be parsed
+    parse_error: Option<String>,
+}
 
-            title.sort_unstable();
-            title.dedup();
-            title.shrink_to_fit();
+fn load_source(
+    source: &Path,
+    format: &dyn Source,
+    analysis: &Analysis,
+) -> Result<LoadedSource, StrError> {
+    let records;
+    let mut counts = ImportCounts {
+        sources: 1,
+        ..Default::default()
+    };
 
-            let mut words = content
-                .split_whitespace()
-                .filter_map(canonicalize)
-                .collect::<Vec<_>>();
+    // shorten peak memory usage time by deallocating `content` after this block
+    {
+        let content = &mut read_content(source)?;
+        content.shrink_to_fit();
+        // stdin is counted as it was decompressed, there is no file size for it
+        counts.bytes = source_size(source).unwrap_or(content.len() as u64);
 
-            if words.len() < 10 {
-                return None;
+        records = format.read_pages(source, content)?;
+    }
+    counts.records = records.len() as u64;
+
+    let mut raw_pages = Vec::with_capacity(records.len());
+    let mut parse_error = None;
+    for record in records {
+        match record {
+            Ok(raw_page) => raw_pages.push(raw_page),
+            Err(err) => {
+                counts.parse_errors += 1;
+                parse_error = parse_error.or(Some(err));
             }
+        }
+    }
 
-            add_pairs(&mut words);
-
-            words.sort_unstable();
-            words.dedup();
-            words.shrink_to_fit();
-
-            if lang == Lang::Eng || lang == Lang::Spa || lang == Lang::Fra {
-                Some((url, Page { lang, title, words }))
-            } else {
-                None
-            }
-        })
+    let analyzed = raw_pages
+        .into_par_iter()
+        .map(|raw_page| analyze_page(raw_page, analysis))
         .collect::<Vec<_>>();
 
-    pages.shrink_to_fit();
-
-    Ok(pages)
-}
-
-fn path_to_files(path: String) -> Vec<PathBuf> {
-    let path = PathBuf::from(path);
-    if path.is_file() {
-        return vec![path];
-    }
-
-    let mut files = Vec::new();
-    if let Ok(dir) = read_dir(&path) {
-        for entry in dir {
-            if let Ok(entry) = entry {
-                let entry = entry.path();
-                let file_name = entry.to_str().unwrap();
-                if entry.is_file()
-                    && (file_name.ends_with(".wet") || file_name.ends_with(".wet.gz"))
-                {
-                    files.push(entry.to_owned());
-                }
-            }
+    let mut pages = Vec::with_capacity(analyzed.len());
+    for page in analyzed {
+        match page {
+            Ok(page) => pages.push(page),
+            Err(Dropped::Language) => counts.language += 1,
+            Err(Dropped::TooShort) => counts.too_short += 1,
         }
-    } else {
-        panic!("ERROR: invalid path provided! Path was {}", path.display());
     }
+    pages.shrink_to_fit();
 
-    files
+    Ok(LoadedSource {
+        pages,
+        counts,
+        parse_error,
+    })
 }
 
 impl Database {
-    pub fn import(&mut self, sources: Vec<String>, chunk_size: usize) -> Result<(), StrError> {
-        let now = Instant::now();
+    pub fn import(
+        &mut self,
+        sources: Vec<String>,
+        chunk_size: usize,
+        skip_near_duplicates: bool,
+        compact: bool,
+        format: Format,
+        log_format: LogFormat,
+    ) -> Result<(), StrError> {
+        let format = format.source();
+        self.persist_config()?;
+        let analysis = self.config().analysis.clone();
 
-        println!("loading source list");
         let sources = sources
             .into_par_iter()
-            .flat_map(path_to_files)
+            .flat_map(|path| path_to_files(path, format))
             .collect::<Vec<_>>();
 
+        let mut progress = Progress::new(log_format, &sources);
+        progress.event(
+            "start",
+            &format!("importing {} sources", sources.len()),
+            json!({ "sources": sources.len() }),
+        );
+
         let chunk_offset = self.num_stores();
 
-        sources
-            .chunks(chunk_size)
-            .enumerate()
-            .for_each(|(chunk_num, chunk)| {
-                let now = Instant::now();
-                let chunk_len = chunk.len();
-                println!("loading {} sources", chunk_len);
-                let results = chunk
-                    .into_par_iter()
-                    .cloned()
-                    .map(load_source)
-                    .collect::<Vec<_>>();
+        self.load_digests()?;
+        if skip_near_duplicates {
+            self.load_simhash_buckets()?;
+        }
 
-                println!("sources loaded, now importing into database");
+        for (chunk_num, chunk) in sources.chunks(chunk_size).enumerate() {
+            let now = Instant::now();
+            let results = chunk
+                .into_par_iter()
+                .map(|source| load_source(source, format, &analysis))
+                .collect::<Vec<_>>();
 
-                // sequential segment, generate URL IDs then persist the URL database
-                let mut results = results
-                    .into_iter()
-                    .filter_map(|pages| {
-                        let pages = match pages {
-                            Ok(pages) => pages,
-                            Err(err) => {
-                                eprintln!("Error: {}", err.0);
-                                return None;
-                            }
+            // sequential segment, generate URL IDs then persist the URL database
+            let mut loaded = Vec::with_capacity(results.len());
+            for (source, result) in chunk.iter().zip(results) {
+                let LoadedSource {
+                    pages,
+                    mut counts,
+                    parse_error,
+                } = match result {
+                    Ok(loaded) => loaded,
+                    Err(err) => {
+                        progress.source_failed(source, &err.0);
+                        let failed = ImportCounts {
+                            sources: 1,
+                            bytes: source_size(source).unwrap_or(0),
+                            failed_sources: 1,
+                            ..Default::default()
                         };
-                        let mut pages = pages
-                            .into_iter()
-                            .map(|(url, page)| (self.insert_url(url), page))
-                            .collect::<Vec<_>>();
-                        pages.shrink_to_fit();
-                        Some(pages)
+                        progress.add(&failed);
+                        continue;
+                    }
+                };
+                if let Some(err) = parse_error {
+                    progress.parse_errors(source, counts.parse_errors, &err);
+                }
+
+                let mut pages = pages
+                    .into_iter()
+                    .filter_map(|(url, page)| match self.insert_page_url(url, &page.meta) {
+                        Ok(id) => Some((id, page)),
+                        Err(Skipped::Duplicate) => {
+                            counts.duplicates += 1;
+                            None
+                        }
+                        Err(Skipped::NearDuplicate) => {
+                            counts.near_duplicates += 1;
+                            None
+                        }
                     })
                     .collect::<Vec<_>>();
-                results.shrink_to_fit();
-
-                self.persist_urls();
-
-                results.into_iter().enumerate().for_each(|(i, pages)| {
-                    println!("processing segment {}/{}", i + 1, chunk_len);
+                pages.shrink_to_fit();
+                counts.pages = pages.len() as u64;
+                loaded.push((pages, counts));
+            }
 
-                    for (url, page) in pages {
-                        self.insert(url, page)
-                    }
-                });
+            self.persist_urls();
 
-                println!("persisting database");
-                self.persist(Some((chunk_num + chunk_offset) as u64));
+            for (pages, counts) in loaded {
+                for (url, page) in pages {
+                    self.insert(url, page)
+                }
+                progress.add(&counts);
+            }
 
-                println!("segments imported in {}", now.elapsed().readable());
-            });
+            self.persist(Some((chunk_num + chunk_offset) as u64));
+
+            progress.event(
+                "chunk_persisted",
+                &format!("persisted chunk {} in {}", chunk_num + 1, now.elapsed().readable()),
+                json!({
+                    "chunk": chunk_num + 1,
+                    "sources": chunk.len(),
+                    "duration_secs": now.elapsed().as_secs_f64(),
+                }),
+            );
+        }
 
-        println!("sources imported in {}", now.elapsed().readable());
+        progress.finish();
+
+        if compact {
+            let now = Instant::now();
+            let merges = self.compact_with(DEFAULT_FANOUT, DEFAULT_BUFFER_SIZE, &mut |merge| {
+                progress.event(
+                    "compacted",
+                    &format!(
+                        "compacted {} {} stores in {}",
+                        merge.stores,
+                        merge.tag,
+                        merge.duration.readable()
+                    ),
+                    json!({
+                        "tag": merge.tag,
+                        "stores": merge.stores,
+                        "duration_secs": merge.duration.as_secs_f64(),
+                    }),
+                );
+            })?;
+            progress.event(
+                "compaction_done",
+                &format!("performed {} compactions in {}", merges, now.elapsed().readable()),
+                json!({ "compactions": merges }),
+            );
+        }
 
         Ok(())
     }
@@ -2,22 +2,28 @@ use database::Database;
 use errors::StrError;
 use rustyline;
 use rustyline::error::ReadlineError;
+use search::{ResultPager, SearchOptions};
 
 impl Database {
-    pub fn interactive(&mut self) -> Result<(), StrError> {
+    pub fn interactive(&mut self, options: SearchOptions) -> Result<(), StrError> {
         let mut rl = rustyline::Editor::<()>::new();
+        let mut last_results = None;
         loop {
             let readline = rl.readline(">> ");
             match readline {
-                Ok(ref exit) if exit == "exit" => break,
                 Err(ReadlineError::Interrupted) |
                 Err(ReadlineError::Eof) => break,
                 Err(err) => return Err(format!("{:?}", err))?,
-                Ok(line) => {
-                    self.search(
-                        line.split_whitespace().map(|x| x.into()).collect(),
-                    )?
-                }
+                Ok(line) => match line.trim() {
+                    "exit" => break,
+                    line => {
+                        // a query that can't be run ends up back at the prompt, only failing to
+                        // read the next line ends the session
+                        if let Err(err) = self.run_line(line, &options, &mut last_results) {
+                            eprintln!("Error: {}", err.0);
+                        }
+                    }
+                },
             }
         }
 
@@ -25,4 +31,44 @@ impl Database {
         // thousands of objects
         ::std::process::exit(0);
     }
+
+    /// runs a query, or pages through the results of the last one
+    fn run_line(
+        &mut self,
+        line: &str,
+        options: &SearchOptions,
+        last_results: &mut Option<ResultPager>,
+    ) -> Result<(), StrError> {
+        match line {
+            "more" | "next" => match *last_results {
+                Some(ref mut pager) => self.print_page(pager, options.limit)?,
+                None => println!("no previous query to page through"),
+            },
+            line if line.starts_with("more from ") => {
+                let host = line["more from ".len()..].trim().to_lowercase();
+                match *last_results {
+                    Some(ref pager) => {
+                        let mut site_pager = self.more_from_host(pager, &host)?;
+                        self.print_page(&mut site_pager, options.limit)?;
+                        *last_results = Some(site_pager);
+                    }
+                    None => println!("no previous query to page through"),
+                }
+            }
+            query => {
+                // pick up the stores imports and compactions wrote in the meantime
+                let reloaded = self.reload()?;
+                if reloaded > 0 {
+                    println!("reloaded {} shards", reloaded);
+                }
+
+                *last_results = Some(self.search(
+                    query.split_whitespace().map(|x| x.into()).collect(),
+                    options,
+                )?)
+            }
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,50 @@
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::path::Path;
+
+use fs2::FileExt;
+
+use errors::StrError;
+
+/// the file in a data dir that whatever writes to it holds a lock on
+const LOCK_FILE: &str = "xray.lock";
+
+/// An exclusive lock on a data dir, so that only one import, compaction or other writer changes
+/// it at a time. searches don't need it. the lock is released when this is dropped, or when the
+/// process holding it exits in any way
+#[derive(Debug)]
+pub struct DataDirLock {
+    file: File,
+}
+
+impl DataDirLock {
+    /// takes the lock on `data_dir`, creating it if needed, failing right away if another
+    /// process holds it
+    pub fn acquire(data_dir: &Path) -> Result<DataDirLock, StrError> {
+        create_dir_all(data_dir)?;
+        let path = data_dir.join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|err| format!("couldn't open {}: {}", path.display(), err))?;
+
+        if file.try_lock_exclusive().is_err() {
+            Err(format!(
+                "{} is being written to by another xray process, wait for it to finish",
+                data_dir.display()
+            ))?;
+        }
+
+        Ok(DataDirLock { file })
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        // the lock file itself is left in place, removing it could let two processes lock
+        // different files under the same name
+        let _ = self.file.unlock();
+    }
+}
@@ -1,15 +1,27 @@
 extern crate byteorder;
+extern crate bzip2;
 extern crate flate2;
+extern crate fs2;
 #[macro_use]
 extern crate nom;
 extern crate rayon;
 extern crate rayon_hash;
 extern crate rustyline;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
 extern crate structopt;
+extern crate tiny_http;
+extern crate toml;
 extern crate whatlang;
+extern crate xz2;
+extern crate zstd;
 
 use structopt::StructOpt;
 
+use std::path::Path;
 use std::process::exit;
 use std::mem::discriminant;
 
@@ -17,16 +29,32 @@ mod errors;
 mod helpers;
 
 mod commoncrawl;
+mod config;
 mod database;
 mod storage;
 
 mod interactive;
+mod query;
+mod ranking;
 mod search;
+mod snippet;
 mod import;
+mod progress;
+mod lock;
+mod realtime;
+mod server;
+mod sources;
+mod dump;
+mod explain;
+mod snapshot;
 mod stats;
 
 use database::Database;
-use storage::Storage;
+use dump::DumpOptions;
+use lock::DataDirLock;
+use progress::LogFormat;
+use search::SearchOptions;
+use sources::Format;
 
 #[derive(StructOpt, Debug, Clone)]
 #[structopt(name = "xray")]
@@ -34,27 +62,126 @@ use storage::Storage;
 enum XrayCmd {
     #[structopt(name = "interactive")]
     /// Starts in an interactive query mode
-    Interactive,
+    Interactive {
+        #[structopt(flatten)]
+        options: SearchOptions,
+    },
 
     #[structopt(name = "search")]
     /// Performs a single search
-    Search { query: Vec<String> },
+    Search {
+        #[structopt(flatten)]
+        options: SearchOptions,
+        /// The query, put `--` before it if it uses operators starting with `-`, like `-site:`
+        query: Vec<String>,
+    },
 
     #[structopt(name = "import")]
-    /// Imports raw CommonCrawl data into xray
+    /// Imports raw CommonCrawl data, or pages in other formats, into xray
     Import {
         #[structopt(long = "chunk-size", default_value = "36")]
         /// The number of import files to be processed in parallel
         chunk_size: usize,
+        #[structopt(long = "skip-near-duplicates")]
+        /// Skips pages whose SimHash is within a few bits of an already imported page
+        skip_near_duplicates: bool,
+        #[structopt(long = "no-compact")]
+        /// Don't compact the stores after importing
+        no_compact: bool,
+        #[structopt(long = "format", default_value = "wet")]
+        /// The format of the sources: wet, warc, jsonl (one {"url", "title", "text"} object per
+        /// line) or files (directories of .txt, .md and .html files, with their paths as URLs)
+        format: Format,
+        #[structopt(long = "log-format", default_value = "text")]
+        /// How progress is reported: text, or json for one event object per line
+        log_format: LogFormat,
+        /// The files and directories to import, `-` reads one file from stdin. Files compressed
+        /// with gzip, zstd, bzip2 or xz are decompressed whatever they are named
         sources: Vec<String>,
     },
 
+    #[structopt(name = "serve")]
+    /// Serves searches over HTTP, at GET /search?q=..., and indexes pages POSTed to /pages as
+    /// {"url", "title", "text"} objects so that they can be searched right away. pages can only
+    /// be added when a single data dir is served
+    Serve {
+        #[structopt(long = "address", default_value = "127.0.0.1:7700")]
+        /// The address to listen on
+        address: String,
+        #[structopt(long = "flush-interval", default_value = "10")]
+        /// How often, in seconds, the pages added since the last flush are written to disk, pages
+        /// that haven't been flushed yet are lost if the server stops
+        flush_interval: u64,
+    },
+
     #[structopt(name = "optimize")]
     /// Optimizes the database files
     Optimize {
         #[structopt(long = "chunk-size", default_value = "2500000")]
-        /// The number of words per optimization chunk
+        /// The most words written to one optimized store
+        chunk_size: usize,
+        #[structopt(long = "buffer-size", default_value = "67108864")]
+        /// The bytes of memory used to buffer reads and writes while merging each tag's stores
+        buffer_size: usize,
+    },
+
+    #[structopt(name = "compact")]
+    /// Merges similarly sized stores to keep the number of stores per query low, without
+    /// rewriting the whole index like optimize
+    Compact {
+        #[structopt(long = "fanout", default_value = "4")]
+        /// How many similarly sized stores to merge at a time
+        fanout: usize,
+        #[structopt(long = "buffer-size", default_value = "67108864")]
+        /// The bytes of memory used to buffer reads and writes while merging
+        buffer_size: usize,
+    },
+
+    #[structopt(name = "shard")]
+    /// Splits a new data directory into shards, which imports are partitioned across by host and
+    /// queries search in parallel
+    Shard {
+        #[structopt(long = "count")]
+        /// The number of shards
+        count: usize,
+    },
+
+    #[structopt(name = "merge")]
+    /// Merges independently built data directories into a new one, renumbering their pages
+    Merge {
+        #[structopt(long = "into")]
+        /// The data directory to merge into, which must not have any pages yet
+        into: String,
+        #[structopt(long = "chunk-size", default_value = "2500000")]
+        /// The most words written to one merged store
         chunk_size: usize,
+        #[structopt(long = "buffer-size", default_value = "67108864")]
+        /// The bytes of memory used to buffer reads and writes while merging each tag's stores
+        buffer_size: usize,
+        /// The data directories to merge
+        sources: Vec<String>,
+    },
+
+    #[structopt(name = "snapshot")]
+    /// Copies the data directory as it is at this moment, even while it is being imported into,
+    /// sharing the store files with it where possible
+    Snapshot {
+        /// Where to write the snapshot, which must not exist yet
+        dest: String,
+    },
+
+    #[structopt(name = "restore")]
+    /// Checks a snapshot and installs it as the data directory, which must be empty
+    Restore {
+        /// The snapshot to restore
+        snapshot: String,
+    },
+
+    #[structopt(name = "dump")]
+    /// Writes out what is in the stores as JSON lines or TSV, for debugging
+    Dump {
+        #[structopt(flatten)]
+        options: DumpOptions,
     },
 
     #[structopt(name = "rebuild-index")]
@@ -70,9 +197,16 @@ enum XrayCmd {
 #[structopt(name = "xray")]
 /// xray is a primitive search engine that will one day search the internet
 struct Xray {
-    #[structopt(short = "d", long = "data-dir", default_value = "/mnt/d/tmp/")]
-    /// the data directory to store the indexed data in
-    data_dir: String,
+    #[structopt(
+        short = "d",
+        long = "data-dir",
+        env = "XRAY_DATA_DIR",
+        raw(number_of_values = "1")
+    )]
+    /// the data directory to store the indexed data in, can be given several times to search
+    /// several data directories together with search, interactive or serve, which then doesn't
+    /// take new pages. its settings are kept in the xray.toml in it
+    data_dir: Vec<String>,
 
     #[structopt(subcommand)] command: XrayCmd,
 }
@@ -84,18 +218,110 @@ fn main() {
 
     // rebuild index doesn't actually need to wait around to read the index
     let load_index = discriminant(&RebuildIndex) != discriminant(&args.command);
-    let storage = Storage::new(&args.data_dir, load_index);
-    let mut database = Database::new(storage);
+    let searching = matches!(args.command, Interactive { .. } | Search { .. } | Serve { .. });
+    if args.data_dir.len() > 1 && !searching {
+        eprintln!("only search, interactive and serve can use more than one data dir");
+        exit(1)
+    }
+    // merge is the only command that is given its data dirs some other way
+    if args.data_dir.is_empty() && !matches!(args.command, Merge { .. }) {
+        eprintln!("no data dir given, pass --data-dir or set XRAY_DATA_DIR");
+        exit(1)
+    }
+
+    if let Shard { count } = args.command {
+        if let Err(error) = Database::create_shards(&args.data_dir[0], count) {
+            eprintln!("{}", error.0);
+            exit(1)
+        }
+        return;
+    }
+
+    if let Merge {
+        ref into,
+        ref sources,
+        chunk_size,
+        buffer_size,
+    } = args.command
+    {
+        if let Err(error) = Database::merge(into, sources, chunk_size, buffer_size) {
+            eprintln!("{}", error.0);
+            exit(1)
+        }
+        return;
+    }
+
+    let result = match args.command {
+        Snapshot { ref dest } => Some(Database::snapshot(&args.data_dir[0], dest)),
+        Restore { ref snapshot } => Some(Database::restore(snapshot, &args.data_dir[0])),
+        _ => None,
+    };
+    if let Some(result) = result {
+        if let Err(error) = result {
+            eprintln!("{}", error.0);
+            exit(1)
+        }
+        return;
+    }
+
+    // only one process writes to a data dir at a time, searches go on while it does
+    let writes = matches!(
+        args.command,
+        Import { .. } | Optimize { .. } | Compact { .. } | RebuildIndex
+    );
+    let _lock = if writes {
+        match DataDirLock::acquire(Path::new(&args.data_dir[0])) {
+            Ok(lock) => Some(lock),
+            Err(error) => {
+                eprintln!("{}", error.0);
+                exit(1)
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut database = match Database::open(&args.data_dir, load_index) {
+        Ok(database) => database,
+        Err(error) => {
+            eprintln!("{}", error.0);
+            exit(1)
+        }
+    };
 
     let result = match args.command {
-        Interactive => database.interactive(),
-        Search { query } => database.search(query),
+        Interactive { options } => database.interactive(options),
+        Search { options, query } => database.search(query, &options).map(|_| ()),
         Import {
             chunk_size,
+            skip_near_duplicates,
+            no_compact,
+            format,
+            log_format,
+            sources,
+        } => database.import(
             sources,
-        } => database.import(sources, chunk_size),
-        Optimize { chunk_size } => database.optimize(chunk_size),
+            chunk_size,
+            skip_near_duplicates,
+            !no_compact,
+            format,
+            log_format,
+        ),
+        Serve {
+            ref address,
+            flush_interval,
+        } => database.serve(address, flush_interval),
+        Optimize {
+            chunk_size,
+            buffer_size,
+        } => database.optimize(chunk_size, buffer_size),
+        Compact {
+            fanout,
+            buffer_size,
+        } => database.compact(fanout, buffer_size),
+        Dump { options } => database.dump(&args.data_dir[0], &options),
         RebuildIndex => database.rebuild_index(),
+        Shard { .. } | Merge { .. } | Snapshot { .. } | Restore { .. } => unreachable!(),
         Stats => database.stats(),
     };
 
@@ -0,0 +1,260 @@
+use std::fs::metadata;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use helpers::ReadableDuration;
+
+/// the least time between two progress reports
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How `import` reports what it is doing, chosen with `--log-format`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// lines of text for people to read
+    Text,
+    /// one JSON object per line, each an event with an `event` name and the seconds since the
+    /// import started as `elapsed_secs`
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<LogFormat, String> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {}, expected text or json", format)),
+        }
+    }
+}
+
+/// What has been read from sources and what became of it
+#[derive(Clone, Debug, Default)]
+pub struct ImportCounts {
+    pub sources: u64,
+    /// the size of the sources as they were read, before decompressing them
+    pub bytes: u64,
+    /// the records read from the sources, each of which is imported as a page or dropped
+    pub records: u64,
+    pub pages: u64,
+    /// records dropped for not being in one of the languages imported
+    pub language: u64,
+    /// records dropped for having too few words
+    pub too_short: u64,
+    pub duplicates: u64,
+    pub near_duplicates: u64,
+    /// records that couldn't be parsed, which are skipped
+    pub parse_errors: u64,
+    /// sources that couldn't be read, none of their records are counted
+    pub failed_sources: u64,
+}
+
+impl ImportCounts {
+    pub fn add(&mut self, other: &ImportCounts) {
+        self.sources += other.sources;
+        self.bytes += other.bytes;
+        self.records += other.records;
+        self.pages += other.pages;
+        self.language += other.language;
+        self.too_short += other.too_short;
+        self.duplicates += other.duplicates;
+        self.near_duplicates += other.near_duplicates;
+        self.parse_errors += other.parse_errors;
+        self.failed_sources += other.failed_sources;
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.language + self.too_short + self.duplicates + self.near_duplicates + self.parse_errors
+    }
+}
+
+/// the size of a source file, None for stdin or files that can't be read
+pub fn source_size(source: &Path) -> Option<u64> {
+    metadata(source).ok().filter(|meta| meta.is_file()).map(|meta| meta.len())
+}
+
+/// a number of bytes in the largest unit it is at least one of
+fn readable_bytes(bytes: f64) -> String {
+    let units = ["bytes", "KB", "MB", "GB", "TB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, units[unit])
+}
+
+/// Tracks how far an import is through its sources, and writes out its events as text or JSON
+pub struct Progress {
+    format: LogFormat,
+    start: Instant,
+    last_report: Option<Instant>,
+    total_sources: u64,
+    /// the size of every source, None when some of them are read from stdin
+    total_bytes: Option<u64>,
+    done: ImportCounts,
+}
+
+impl Progress {
+    pub fn new(format: LogFormat, sources: &[PathBuf]) -> Progress {
+        let total_bytes = sources.iter().map(|source| source_size(source)).sum();
+
+        Progress {
+            format,
+            start: Instant::now(),
+            last_report: None,
+            total_sources: sources.len() as u64,
+            total_bytes,
+            done: ImportCounts::default(),
+        }
+    }
+
+    /// writes out an event, as `message` for text and as the `fields` object for JSON
+    pub fn event(&self, event: &str, message: &str, mut fields: Value) {
+        match self.format {
+            LogFormat::Text => println!("{}", message),
+            LogFormat::Json => {
+                fields["event"] = json!(event);
+                fields["elapsed_secs"] = json!(self.elapsed_secs());
+                println!("{}", fields);
+            }
+        }
+    }
+
+    /// a source that couldn't be imported, on stderr for text
+    pub fn source_failed(&self, source: &Path, error: &str) {
+        match self.format {
+            LogFormat::Text => eprintln!("Error: {}", error),
+            LogFormat::Json => self.event(
+                "source_failed",
+                error,
+                json!({ "source": source.display().to_string(), "error": error }),
+            ),
+        }
+    }
+
+    /// records of a source that couldn't be parsed and were skipped, with the first one's error,
+    /// on stderr for text
+    pub fn parse_errors(&self, source: &Path, count: u64, first_error: &str) {
+        let message = format!(
+            "skipped {} records of {} that couldn't be parsed, the first: {}",
+            count,
+            source.display(),
+            first_error
+        );
+        match self.format {
+            LogFormat::Text => eprintln!("Warning: {}", message),
+            LogFormat::Json => self.event(
+                "parse_errors",
+                &message,
+                json!({
+                    "source": source.display().to_string(),
+                    "records": count,
+                    "first_error": first_error,
+                }),
+            ),
+        }
+    }
+
+    /// records sources that are done, and reports on the import if the last report was long
+    /// enough ago
+    pub fn add(&mut self, counts: &ImportCounts) {
+        self.done.add(counts);
+
+        if self.last_report.is_none_or(|last| last.elapsed() >= REPORT_INTERVAL) {
+            self.report("progress");
+            self.last_report = Some(Instant::now());
+        }
+    }
+
+    /// reports the totals once the import is done
+    pub fn finish(&self) {
+        self.report("done");
+    }
+
+    fn elapsed_secs(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    /// how much longer the import is expected to take, going by how much of the sources, by
+    /// size or by count when their sizes aren't known, has been done so far
+    fn eta(&self) -> Option<Duration> {
+        let fraction = match self.total_bytes {
+            Some(total) if total > 0 => self.done.bytes as f64 / total as f64,
+            _ if self.total_sources > 0 => self.done.sources as f64 / self.total_sources as f64,
+            _ => return None,
+        };
+        if fraction <= 0.0 {
+            return None;
+        }
+
+        let remaining = self.elapsed_secs() * (1.0 - fraction.min(1.0)) / fraction;
+        Some(Duration::from_millis((remaining * 1000.0) as u64))
+    }
+
+    fn report(&self, event: &str) {
+        let done = &self.done;
+        let secs = self.elapsed_secs().max(0.001);
+        let per_sec = |count: u64| count as f64 / secs;
+        let eta = if event == "done" { None } else { self.eta() };
+
+        let mut message = format!(
+            "{} {}/{} sources, {} at {}/s, {} records at {:.0}/s, {} pages at {:.0}/s, \
+             dropped {} ({} language, {} too short, {} duplicates, {} near duplicates, \
+             {} parse errors)",
+            if event == "done" { "imported" } else { "importing" },
+            done.sources,
+            self.total_sources,
+            readable_bytes(done.bytes as f64),
+            readable_bytes(per_sec(done.bytes)),
+            done.records,
+            per_sec(done.records),
+            done.pages,
+            per_sec(done.pages),
+            done.dropped(),
+            done.language,
+            done.too_short,
+            done.duplicates,
+            done.near_duplicates,
+            done.parse_errors
+        );
+        if done.failed_sources > 0 {
+            message += &format!(", {} sources failed", done.failed_sources);
+        }
+        if let Some(eta) = eta {
+            message += &format!(", ETA {}", eta.readable());
+        } else if event == "done" {
+            message += &format!(" in {}", self.start.elapsed().readable());
+        }
+
+        self.event(
+            event,
+            &message,
+            json!({
+                "sources": done.sources,
+                "total_sources": self.total_sources,
+                "bytes": done.bytes,
+                "total_bytes": self.total_bytes,
+                "records": done.records,
+                "pages": done.pages,
+                "bytes_per_sec": per_sec(done.bytes),
+                "records_per_sec": per_sec(done.records),
+                "pages_per_sec": per_sec(done.pages),
+                "dropped": {
+                    "language": done.language,
+                    "too_short": done.too_short,
+                    "duplicate": done.duplicates,
+                    "near_duplicate": done.near_duplicates,
+                    "parse_error": done.parse_errors,
+                },
+                "failed_sources": done.failed_sources,
+                "eta_secs": eta.map(|eta| eta.as_secs()),
+            }),
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use whatlang::Lang;
+
+use errors::StrError;
+
+/// A parsed query: the words to search for, plus the operators restricting which pages match
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    /// words searched for in both the title and the content
+    pub words: Vec<String>,
+    /// words only searched for in the title, from `title:`
+    pub title_words: Vec<String>,
+    /// words only searched for in the content, from `body:`
+    pub body_words: Vec<String>,
+    /// words only searched for in the URL, from `url:`
+    pub url_words: Vec<String>,
+    pub lang: Option<Lang>,
+    /// inclusive lower bound on the crawl date, as a `YYYY[-MM[-DD]]` prefix
+    pub after: Option<String>,
+    /// exclusive upper bound on the crawl date, as a `YYYY[-MM[-DD]]` prefix
+    pub before: Option<String>,
+    /// hosts or registered domains to restrict results to, any of which may match
+    pub sites: Vec<String>,
+    /// hosts or registered domains to leave out of the results
+    pub excluded_sites: Vec<String>,
+    /// fragments that must all appear in the URL of a result
+    pub inurl: Vec<String>,
+    /// exact hosts to restrict results to, used by `more from` in interactive mode
+    pub hosts: Vec<String>,
+}
+
+/// the number of days in a month of a year
+fn days_in_month(year: u32, month: u32) -> u32 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// checks that a date is an existing `YYYY`, `YYYY-MM` or `YYYY-MM-DD` date, which compare
+/// correctly against the `YYYY-MM-DD` date buckets as plain strings
+pub fn parse_date(date: &str) -> Result<String, StrError> {
+    let well_formed = date.split('-').enumerate().all(|(i, part)| {
+        let len = if i == 0 { 4 } else { 2 };
+        i < 3 && part.len() == len && part.chars().all(|c| c.is_ascii_digit())
+    });
+    if !well_formed {
+        Err(format!("invalid date {}, expected YYYY-MM-DD", date))?;
+    }
+
+    let parts = date.split('-').map(|part| part.parse().unwrap()).collect::<Vec<u32>>();
+    if let Some(&month) = parts.get(1) {
+        if !(1..=12).contains(&month) {
+            Err(format!("invalid date {}, there is no month {}", date, month))?;
+        }
+    }
+    if let Some(&day) = parts.get(2) {
+        if !(1..=days_in_month(parts[0], parts[1])).contains(&day) {
+            Err(format!("invalid date {}, there is no day {} in that month", date, day))?;
+        }
+    }
+
+    Ok(date.to_string())
+}
+
+impl Query {
+    pub fn parse(terms: Vec<String>) -> Result<Query, StrError> {
+        let mut query = Query::default();
+
+        for term in terms {
+            if let Some(date) = term.strip_prefix("after:") {
+                query.after = Some(parse_date(date)?);
+            } else if let Some(date) = term.strip_prefix("before:") {
+                query.before = Some(parse_date(date)?);
+            } else if let Some(site) = term.strip_prefix("site:") {
+                query.sites.push(site.to_lowercase());
+            } else if let Some(site) = term.strip_prefix("-site:") {
+                query.excluded_sites.push(site.to_lowercase());
+            } else if let Some(fragment) = term.strip_prefix("inurl:") {
+                query.inurl.push(fragment.to_lowercase());
+            } else if let Some(word) = term.strip_prefix("title:") {
+                query.title_words.push(word.to_string());
+            } else if let Some(word) = term.strip_prefix("body:") {
+                query.body_words.push(word.to_string());
+            } else if let Some(word) = term.strip_prefix("url:") {
+                query.url_words.push(word.to_string());
+            } else {
+                query.words.push(term);
+            }
+        }
+
+        Ok(query)
+    }
+
+    /// every word searched for, regardless of field
+    pub fn all_words(&self) -> Vec<String> {
+        self.words
+            .iter()
+            .chain(&self.title_words)
+            .chain(&self.body_words)
+            .chain(&self.url_words)
+            .cloned()
+            .collect()
+    }
+
+    /// whether a `YYYY-MM-DD` date bucket falls inside the requested date range
+    pub fn matches_date(&self, date: &str) -> bool {
+        self.after.as_ref().is_none_or(|after| date >= after.as_str())
+            && self.before.as_ref().is_none_or(|before| date < before.as_str())
+    }
+
+    pub fn has_date_range(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dates() {
+        for date in &["2019", "2019-01", "2019-01-31"] {
+            assert_eq!(parse_date(date).unwrap(), date.to_string());
+        }
+        for date in &["", "19", "2019-1", "2019-01-1", "2019/01/01", "2019-01-01-01", "20x9"] {
+            assert!(parse_date(date).is_err(), "{} parsed", date);
+        }
+
+        // well formed, but not a date
+        let dates = [
+            "2019-00", "2019-13", "2019-13-45", "2019-01-00", "2019-04-31", "2019-02-29",
+            "1900-02-29",
+        ];
+        for date in &dates {
+            assert!(parse_date(date).is_err(), "{} parsed", date);
+        }
+        assert!(parse_date("2020-02-29").is_ok());
+        assert!(parse_date("2000-02-29").is_ok());
+    }
+
+    #[test]
+    fn parses_date_operators() {
+        let terms = vec!["after:2019-02", "before:2020-01-01", "rust"];
+        let query = Query::parse(terms.into_iter().map(|term| term.to_string()).collect()).unwrap();
+        assert_eq!(query.after, Some("2019-02".to_string()));
+        assert_eq!(query.before, Some("2020-01-01".to_string()));
+
+        assert!(Query::parse(vec!["after:2019-1".to_string()]).is_err());
+        assert!(Query::parse(vec!["after:2019-13-45".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::Error;
+
+use storage::TermCursor;
+
+/// Finds the `k` best scoring pages document-at-a-time with MaxScore pruning: terms whose scores
+/// together can't lift a page into the top `k` are only checked for pages that one of the other
+/// terms matched, so their postings are skipped over rather than read. a term scores the same for
+/// every page, which makes its score the exact bound that the pruning goes by rather than a bound
+/// stored in the index, see `TermCursor`.
+/// returns the (url id, score) pairs, best first with ties broken by url id
+pub fn top_k<F: Fn(u64) -> bool>(
+    mut terms: Vec<TermCursor>,
+    k: usize,
+    allows: F,
+) -> Result<Vec<(u64, usize)>, Error> {
+    if k == 0 {
+        return Ok(Vec::new());
+    }
+
+    terms.sort_by_key(|term| term.score);
+
+    // bounds[i] is the most a page can score from terms[..i]
+    let mut bounds = vec![0];
+    for term in &terms {
+        let bound = bounds[bounds.len() - 1] + term.score;
+        bounds.push(bound);
+    }
+
+    // the worst of the current top `k` is at the top of the heap, pages scoring the same come
+    // after the ones already found since pages are visited in id order. it grows with the
+    // matches, `k` can be far more than there are
+    let mut heap = BinaryHeap::new();
+    let mut threshold = 0;
+    // terms[..essential] can't reach the threshold on their own
+    let mut essential = 0;
+
+    while let Some(page) = terms[essential..]
+        .iter()
+        .filter_map(|term| term.current())
+        .min()
+    {
+        let mut score = 0;
+        for term in &mut terms[essential..] {
+            if term.current() == Some(page) {
+                score += term.score;
+                term.advance()?;
+            }
+        }
+
+        if !allows(page) {
+            continue;
+        }
+
+        for i in (0..essential).rev() {
+            if heap.len() == k && score + bounds[i + 1] <= threshold {
+                break;
+            }
+            if terms[i].seek(page)? == Some(page) {
+                score += terms[i].score;
+            }
+        }
+
+        if heap.len() < k || score > threshold {
+            heap.push(Reverse((score, Reverse(page))));
+            if heap.len() > k {
+                heap.pop();
+            }
+            if heap.len() == k {
+                threshold = (heap.peek().unwrap().0).0;
+                while essential < terms.len() && bounds[essential + 1] <= threshold {
+                    essential += 1;
+                }
+            }
+        }
+    }
+
+    let mut results = heap.into_iter()
+        .map(|Reverse((score, Reverse(page)))| (page, score))
+        .collect::<Vec<_>>();
+    results.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use storage::PostingCursor;
+
+    /// a term over the postings, split into two segments like stores persisted at different times
+    fn term(word: &str, score: usize, postings: &[u64]) -> TermCursor {
+        let (odd, even) = postings.iter().partition::<Vec<u64>, _>(|&&id| id % 2 == 1);
+        let segments = vec![PostingCursor::in_memory(odd), PostingCursor::in_memory(even)];
+        TermCursor::new(word.to_string(), score, segments)
+    }
+
+    /// every page that matched a term, scored and ranked without any pruning
+    fn exhaustive<F: Fn(u64) -> bool>(
+        terms: &[(usize, Vec<u64>)],
+        k: usize,
+        allows: F,
+    ) -> Vec<(u64, usize)> {
+        let mut scores = ::std::collections::BTreeMap::new();
+        for (score, postings) in terms {
+            for &id in postings {
+                *scores.entry(id).or_insert(0) += score;
+            }
+        }
+
+        let mut ranked = scores
+            .into_iter()
+            .filter(|&(id, _)| allows(id))
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(k);
+        ranked
+    }
+
+    #[test]
+    fn top_k_matches_exhaustive_ranking() {
+        // a small linear congruential generator keeps the postings the same on every run
+        let mut state = 7u64;
+        let mut next = move || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            state >> 33
+        };
+
+        let mut terms = Vec::new();
+        for (score, density) in [(3, 2), (4, 3), (5, 10), (8, 25), (12, 60), (24, 200)] {
+            let postings = (0..5000).filter(|_| next() % density == 0).collect::<Vec<_>>();
+            terms.push((score, postings));
+        }
+
+        let allows = |id: u64| id % 7 != 3;
+        for &k in &[1, 2, 10, 100, 1000, 10000] {
+            let cursors = terms
+                .iter()
+                .enumerate()
+                .map(|(i, (score, postings))| term(&i.to_string(), *score, postings))
+                .collect();
+            let ranked = top_k(cursors, k, allows).unwrap();
+            assert_eq!(ranked, exhaustive(&terms, k, allows), "k = {}", k);
+        }
+    }
+
+    #[test]
+    fn top_k_without_results() {
+        assert!(top_k(vec![term("word", 3, &[1, 2, 3])], 0, |_| true).unwrap().is_empty());
+        assert!(top_k(Vec::new(), 10, |_| true).unwrap().is_empty());
+        assert!(top_k(vec![term("word", 3, &[1, 2, 3])], 10, |_| false).unwrap().is_empty());
+    }
+}
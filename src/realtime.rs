@@ -0,0 +1,69 @@
+use database::{Database, Skipped};
+use errors::StrError;
+use import::{analyze_page, Dropped};
+use sources::RawPage;
+use storage::LoadedIndices;
+
+impl Database {
+    /// indexes a single page in memory, where it can be searched right away alongside the stored
+    /// pages until it is flushed. returns the page's id. the data dir is locked
+    /// from the first page added until the flush, no other process can import into it meanwhile
+    pub fn add_page(&mut self, raw_page: RawPage) -> Result<u64, StrError> {
+        if self.lock()? {
+            self.persist_config()?;
+            if self.reload()? > 0 {
+                // another process imported pages since the indices were loaded, the ids of the
+                // new pages come after theirs and duplicates of them are skipped
+                self.load_digests()?;
+            }
+        }
+
+        let url = raw_page.url.clone();
+        let (url, page) = match analyze_page(raw_page, &self.config().analysis) {
+            Ok(analyzed) => analyzed,
+            Err(Dropped::Language) => Err(format!("{} is not in a language imported", url))?,
+            Err(Dropped::TooShort) => Err(format!("{} is too short to be indexed", url))?,
+        };
+
+        let id = match self.insert_page_url(url.clone(), &page.meta) {
+            Ok(id) => id,
+            Err(Skipped::Duplicate) => Err(format!("{} is a duplicate of an indexed page", url))?,
+            Err(Skipped::NearDuplicate) => {
+                Err(format!("{} is a near duplicate of an indexed page", url))?
+            }
+        };
+        self.insert(id, page);
+
+        Ok(id)
+    }
+
+    /// the number of pages added since they were last flushed
+    pub fn num_recent(&self) -> usize {
+        self.shards().iter().map(|storage| storage.num_recent()).sum()
+    }
+
+    /// writes the pages added since the last flush out to new stores on each shard and loads the
+    /// indices with them, while the pages can still be searched in memory. `finish_flush` swaps
+    /// the indices in
+    pub fn write_recent(&self) -> Result<Vec<(usize, LoadedIndices)>, StrError> {
+        let mut written = Vec::new();
+        for (shard, storage) in self.shards().iter().enumerate() {
+            if let Some(indices) = storage.write_recent()? {
+                written.push((shard, indices));
+            }
+        }
+        Ok(written)
+    }
+
+    /// swaps in the indices `write_recent` loaded and unlocks the data dir, returns how many
+    /// pages were flushed
+    pub fn finish_flush(&mut self, written: Vec<(usize, LoadedIndices)>) -> usize {
+        let num_recent = self.num_recent();
+        for (shard, indices) in written {
+            self.shards_mut()[shard].finish_flush(indices);
+        }
+        self.unlock();
+
+        num_recent
+    }
+}
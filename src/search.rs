@@ -1,36 +1,350 @@
 use std::time::Instant;
 
-use whatlang::Lang;
+use structopt::StructOpt;
+use whatlang::{Detector, Lang};
 
-use database::Database;
+use database::{Database, Diversity, HiddenResults, SearchResult};
 use errors::StrError;
 use helpers::ReadableDuration;
+use query::{parse_date, Query};
+
+#[derive(StructOpt, Debug, Clone)]
+pub struct SearchOptions {
+    #[structopt(long = "lang")]
+    /// The language to search when the query language can't be detected confidently,
+    /// otherwise all languages are searched
+    pub lang: Option<String>,
+
+    #[structopt(long = "lang-confidence", default_value = "0.5")]
+    /// The minimum confidence (0.0 - 1.0) required to trust the detected query language
+    pub lang_confidence: f64,
+
+    #[structopt(long = "limit", default_value = "10")]
+    /// The number of results to show per page
+    pub limit: usize,
+
+    #[structopt(long = "offset", default_value = "0")]
+    /// The number of results to skip before the first one shown
+    pub offset: usize,
+
+    #[structopt(long = "since")]
+    /// Only return pages crawled on or after this date (YYYY-MM-DD), same as `after:`
+    pub since: Option<String>,
+
+    #[structopt(long = "until")]
+    /// Only return pages crawled before this date (YYYY-MM-DD), same as `before:`
+    pub until: Option<String>,
+
+    #[structopt(long = "per-host", default_value = "2")]
+    /// The most results shown from any one host, 0 for no limit
+    pub per_host: usize,
+
+    #[structopt(long = "no-collapse")]
+    /// Also show results that are near duplicates of a higher ranked result
+    pub no_collapse: bool,
+
+    #[structopt(long = "explain")]
+    /// Shows how each result scored: the words matched in its title, body and URL, how many
+    /// pages have them, what each added to the score and whether the language filter passed
+    pub explain: bool,
+}
+
+/// the most results of a query that are ranked, which pages can go through
+pub const MAX_RANKED: usize = 1000;
+
+/// the best results of a query, ranked once when it is run, which the pages go through
+pub struct ResultPager {
+    pub query: Query,
+    pub terms: Vec<String>,
+    pub ranked: Vec<(u64, usize)>,
+    /// how many of the ranked results have been gone through
+    pub offset: usize,
+    /// how many results have been shown or skipped, which leaves out the hidden ones
+    pub shown: usize,
+    pub diversity: Diversity,
+    /// whether the score of each result printed is explained
+    pub explain: bool,
+}
+
+impl ResultPager {
+    pub fn new(query: Query, ranked: Vec<(u64, usize)>, diversity: Diversity) -> ResultPager {
+        ResultPager {
+            terms: query.all_words(),
+            query,
+            ranked,
+            offset: 0,
+            shown: 0,
+            diversity,
+            explain: false,
+        }
+    }
+
+    /// whether every matching result has been ranked, rather than the first `MAX_RANKED`
+    pub fn is_complete(&self) -> bool {
+        self.ranked.len() < MAX_RANKED
+    }
+}
 
 impl Database {
-    pub fn search(&mut self, words: Vec<String>) -> Result<(), StrError> {
-        // for detecting query language, only a limited set are supported to reduce
-        // false positives on short strings
-
-        // let detector = Detector::with_whitelist(vec![
-        //     Lang::Eng,
-        //     Lang::Spa,
-        //     Lang::Fra,
-        //     Lang::Cmn,
-        //     Lang::Jpn,
-        //     Lang::Kor,
-        //     Lang::Rus,
-        // ]);
-        // let lang = detector.detect(&words.join(" "));
-        // println!("{:?}", lang);
+    /// detects the language of a query, considering only the languages present in the index to
+    /// reduce false positives on short strings
+    pub fn detect_lang(&self, words: &[String]) -> Option<(Lang, f64)> {
+        let langs = self.langs();
+        if langs.is_empty() {
+            return None;
+        }
+
+        let detector = Detector::with_whitelist(langs);
+        let info = detector.detect(&words.join(" "))?;
+
+        Some((info.lang(), info.confidence()))
+    }
+
+    pub fn search(
+        &mut self,
+        words: Vec<String>,
+        options: &SearchOptions,
+    ) -> Result<ResultPager, StrError> {
+        let default_lang = match options.lang {
+            Some(ref code) => Some(Lang::from_code(code.as_str())
+                .ok_or_else(|| format!("unknown language code: {}", code))?),
+            None => None,
+        };
+
+        let mut query = Query::parse(words)?;
+        if let Some(ref since) = options.since {
+            query.after = Some(parse_date(since)?);
+        }
+        if let Some(ref until) = options.until {
+            query.before = Some(parse_date(until)?);
+        }
+
+        query.lang = match self.detect_lang(&query.all_words()) {
+            Some((lang, confidence)) if confidence >= options.lang_confidence => {
+                println!("language: {} (confidence {:.2})", lang.code(), confidence);
+                Some(lang)
+            }
+            detected => {
+                let confidence = detected.map(|(_, confidence)| confidence).unwrap_or(0.0);
+                match default_lang {
+                    Some(lang) => println!(
+                        "language: {} (default, detection confidence {:.2})",
+                        lang.code(),
+                        confidence
+                    ),
+                    None => println!(
+                        "language: all (detection confidence {:.2})",
+                        confidence
+                    ),
+                }
+                default_lang
+            }
+        };
 
         let now = Instant::now();
 
-        self.query(words, Some(Lang::Eng));
+        let diversity = Diversity::new(!options.no_collapse, options.per_host);
+        let ranked = self.query(&query, MAX_RANKED)?;
+        let mut pager = ResultPager::new(query, ranked, diversity);
+        pager.explain = options.explain;
+        // the results before the offset are paged past without printing them, so that the same
+        // hosts and near duplicates are hidden after it as when paging to it
+        self.next_results(&mut pager, options.offset);
+        self.print_page(&mut pager, options.limit)?;
 
         let elapsed = now.elapsed().readable();
         println!("performed query in {}", elapsed);
 
+        Ok(pager)
+    }
+
+    /// the next `limit` results of a pager that aren't hidden and the ones that are hidden among
+    /// them, advancing the pager past them
+    pub fn next_results(
+        &self,
+        pager: &mut ResultPager,
+        limit: usize,
+    ) -> (Vec<SearchResult>, HiddenResults) {
+        let mut results = Vec::new();
+        let mut hidden = HiddenResults::default();
+
+        while results.len() < limit && pager.offset < pager.ranked.len() {
+            let (mut page, page_hidden) = self.get_diverse_results(
+                &pager.ranked,
+                &pager.terms,
+                &mut pager.offset,
+                limit - results.len(),
+                &mut pager.diversity,
+            );
+            results.append(&mut page);
+            hidden.extend(page_hidden);
+        }
+
+        pager.shown += results.len();
+        (results, hidden)
+    }
+
+    /// prints the next `limit` results and advances the pager past them
+    pub fn print_page(&self, pager: &mut ResultPager, limit: usize) -> Result<(), StrError> {
+        let first_shown = pager.shown;
+        let (results, hidden) = self.next_results(pager, limit);
+
+        let total = if pager.is_complete() {
+            format!("{}", pager.ranked.len())
+        } else {
+            format!("at least {}", pager.ranked.len())
+        };
+
+        if pager.ranked.is_empty() {
+            println!("no matches found");
+        } else if results.is_empty() {
+            println!("no more results ({} total)", total);
+        } else {
+            // hidden results are matches too, but aren't counted in the range shown
+            let mut range = format!(
+                "results {}-{} of {} matches",
+                first_shown + 1,
+                pager.shown,
+                total
+            );
+            if hidden.total() > 0 {
+                range += &format!(", {} hidden", hidden.total());
+            }
+            println!("{}", range);
+        }
+
+        for result in &results {
+            println!("{}", result.url);
+            if let Some(ref meta) = result.meta {
+                println!("    {} | {} | {}", meta.title, meta.date, meta.lang.code());
+            }
+            if let Some(ref data_dir) = result.data_dir {
+                println!("    in {}", data_dir);
+            }
+            if let Some(ref snippet) = result.snippet {
+                println!("    {}", snippet.highlighted("\x1b[1m", "\x1b[0m"));
+            }
+            for alternate in result.alternates.iter().take(3) {
+                println!("    also at {}", alternate);
+            }
+            if result.alternates.len() > 3 {
+                println!("    also at {} more URLs", result.alternates.len() - 3);
+            }
+            if pager.explain {
+                for line in self.explain(&pager.query, result.id)?.lines() {
+                    println!("    {}", line);
+                }
+            }
+        }
+
+        if hidden.near_duplicates > 0 {
+            println!(
+                "hid {} near duplicate results, use --no-collapse to show them",
+                hidden.near_duplicates
+            );
+        }
+        for (host, count) in hidden.by_host {
+            println!(
+                "hid {} more results from {}, use `more from {}` in interactive mode or site:{} to show them",
+                count, host, host, host
+            );
+        }
+
         Ok(())
-        // Err("Search has not been implemented yet!")?
+    }
+
+    /// a pager over the results of `pager`'s query that are on `host`, without a per host limit
+    pub fn more_from_host(&self, pager: &ResultPager, host: &str) -> Result<ResultPager, StrError> {
+        let mut query = pager.query.clone();
+        query.hosts = vec![host.to_string()];
+
+        let ranked = self.query(&query, MAX_RANKED)?;
+        let diversity = Diversity::new(pager.diversity.collapse, 0);
+        let mut more = ResultPager::new(query, ranked, diversity);
+        more.explain = pager.explain;
+        Ok(more)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, remove_dir_all};
+    use std::process;
+
+    use super::*;
+    use sources::RawPage;
+
+    const WORDS: [&str; 40] = [
+        "the", "rust", "compiler", "checks", "ownership", "memory", "programs", "people",
+        "about", "safety", "speed", "library", "crate", "build", "tests", "threads", "async",
+        "borrow", "lifetime", "trait", "generic", "macro", "module", "package", "release",
+        "stable", "nightly", "version", "feature", "error", "handling", "pattern", "matching",
+        "closure", "iterator", "vector", "string", "slice", "reference", "pointer",
+    ];
+
+    fn options(offset: usize, limit: usize) -> SearchOptions {
+        SearchOptions {
+            lang: Some("eng".to_string()),
+            lang_confidence: 0.5,
+            limit,
+            offset,
+            since: None,
+            until: None,
+            per_host: 2,
+            no_collapse: false,
+            explain: false,
+        }
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<u64> {
+        results.iter().map(|result| result.id).collect()
+    }
+
+    #[test]
+    fn offset_matches_paging() {
+        let dir = ::std::env::temp_dir().join(format!("xray_offset_{}", process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir(&dir).unwrap();
+        let mut database = Database::open(&[dir.to_str().unwrap().to_string()], true).unwrap();
+
+        // three pages on each host, which the per host limit hides one of, and copies of the
+        // first pages on other hosts, which are collapsed
+        let mut state = 11u64;
+        let mut texts: Vec<String> = Vec::new();
+        for page in 0..36 {
+            let text = if page % 12 == 11 {
+                texts[page / 12 * 3].clone()
+            } else {
+                (0..40)
+                    .map(|_| {
+                        state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+                        WORDS[(state >> 33) as usize % WORDS.len()]
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+            texts.push(text.clone());
+            database
+                .add_page(RawPage {
+                    url: format!("https://host{}.example.com/{}", page / 3, page),
+                    title: Some(format!("rust page {}", page)),
+                    text: format!("this is what people know about the rust compiler {}", text),
+                    date: "2019-01-01".to_string(),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
+        let words = vec!["rust".to_string(), "compiler".to_string()];
+        let mut paged = database.search(words.clone(), &options(0, 10)).unwrap();
+        let second_page = database.next_results(&mut paged, 10).0;
+        assert_eq!(second_page.len(), 10);
+
+        let mut skipped = database.search(words, &options(10, 0)).unwrap();
+        assert_eq!(skipped.shown, 10);
+        assert_eq!(ids(&database.next_results(&mut skipped, 10).0), ids(&second_page));
+        assert_eq!(skipped.shown, paged.shown);
+
+        let _ = remove_dir_all(&dir);
     }
 }
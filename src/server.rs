@@ -0,0 +1,303 @@
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+use rayon_hash::HashMap;
+use serde_json::{self, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use database::Database;
+use errors::StrError;
+use helpers::ReadableDuration;
+use query::Query;
+use search::MAX_RANKED;
+use sources::jsonl::page_from_json;
+
+/// the number of threads answering requests
+const WORKERS: usize = 4;
+
+/// how often the data dir is checked for stores written by other processes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// the largest request body read, in bytes
+const MAX_BODY_LEN: u64 = 16 * 1024 * 1024;
+
+/// the minimum confidence the detected query language needs to restrict a search to it, the
+/// same as the default of `--lang-confidence`
+const LANG_CONFIDENCE: f64 = 0.5;
+
+/// decodes a `application/x-www-form-urlencoded` value
+fn percent_decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.as_bytes().iter();
+    while let Some(&b) = input.next() {
+        match b {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = input.as_slice().get(..2).and_then(|hex| {
+                    u8::from_str_radix(::std::str::from_utf8(hex).ok()?, 16).ok()
+                });
+                match hex {
+                    Some(decoded) => {
+                        bytes.push(decoded);
+                        input.nth(1);
+                    }
+                    None => bytes.push(b),
+                }
+            }
+            b => bytes.push(b),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// the parameters of a URL's query string
+fn query_params(url: &str) -> HashMap<String, String> {
+    let query = url.split_once('?').map_or("", |(_, query)| query);
+    query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+/// a numeric query string parameter, or `default` if it isn't given
+fn usize_param(
+    params: &HashMap<String, String>,
+    name: &str,
+    default: usize,
+) -> Result<usize, StrError> {
+    match params.get(name) {
+        Some(value) => Ok(value
+            .parse()
+            .map_err(|_| format!("{} has to be a number", name))?),
+        None => Ok(default),
+    }
+}
+
+fn json_response(status: u16, body: &Value) -> Response<Cursor<Vec<u8>>> {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+fn error_body(message: &str) -> Value {
+    json!({ "error": message })
+}
+
+impl Database {
+    /// runs a search for the `q` parameter, like the `search` command does, returning a page of
+    /// results without collapsing near duplicates or limiting results per host. with
+    /// `explain=true` each result says how it scored, like `search --explain`. when several data
+    /// dirs are served, each result says which one it is in
+    fn search_json(&self, params: &HashMap<String, String>) -> Result<Value, StrError> {
+        let words = params
+            .get("q")
+            .ok_or("missing the q parameter")?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        // no more results are ranked than a search shows pages of
+        let limit = usize_param(params, "limit", 10)?.min(MAX_RANKED);
+        let offset = usize_param(params, "offset", 0)?.min(MAX_RANKED);
+        let depth = offset.checked_add(limit).ok_or("offset and limit are too large")?;
+        let explain = params.get("explain").is_some_and(|explain| explain == "true");
+
+        let mut query = Query::parse(words)?;
+        query.lang = match self.detect_lang(&query.all_words()) {
+            Some((lang, confidence)) if confidence >= LANG_CONFIDENCE => Some(lang),
+            _ => None,
+        };
+
+        let terms = query.all_words();
+        let ranked = self.query(&query, depth)?;
+        let mut results = Vec::new();
+        for result in self.get_results(&ranked, &terms, offset, limit) {
+            let meta = result.meta.as_ref();
+            let mut json = json!({
+                "id": result.id,
+                "score": result.score,
+                "url": result.url,
+                "title": meta.map(|meta| meta.title.as_str()),
+                "date": meta.map(|meta| meta.date.as_str()),
+                "lang": meta.map(|meta| meta.lang.code()),
+                "snippet": result.snippet.as_ref().map(|snippet| snippet.text.as_str()),
+                "alternates": result.alternates,
+                "data_dir": result.data_dir,
+            });
+            if explain {
+                json["explain"] = self.explain(&query, result.id)?.to_json();
+            }
+            results.push(json);
+        }
+
+        Ok(json!({
+            "lang": query.lang.map(|lang| lang.code().to_string()),
+            "results": results,
+        }))
+    }
+}
+
+/// What the request handlers share
+struct State {
+    database: RwLock<Database>,
+    /// held while pages are added or flushed, so that the pages a flush writes out while searches
+    /// go on are still all the pages in memory when it swaps in the stores it wrote
+    writes: Mutex<()>,
+}
+
+/// writes the pages added since the last flush out to disk, only holding the database's write
+/// lock to swap in the stores written. returns how many pages there were
+fn flush(state: &State) -> Result<usize, StrError> {
+    let _writes = state.writes.lock().unwrap();
+    let written = state.database.read().unwrap().write_recent()?;
+    Ok(state.database.write().unwrap().finish_flush(written))
+}
+
+/// answers one request, with its status code and JSON body
+fn handle(state: &State, request: &mut Request) -> (u16, Value) {
+    let database = &state.database;
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+
+    match (request.method().clone(), path.as_str()) {
+        (Method::Get, "/search") => {
+            let params = query_params(request.url());
+            match database.read().unwrap().search_json(&params) {
+                Ok(body) => (200, body),
+                Err(err) => (400, error_body(&err.0)),
+            }
+        }
+        (Method::Post, "/pages") => {
+            if database.read().unwrap().is_federated() {
+                let message = "pages can't be added while several data dirs are served";
+                return (403, error_body(message));
+            }
+
+            let mut body = String::new();
+            if let Err(err) = request
+                .as_reader()
+                .take(MAX_BODY_LEN)
+                .read_to_string(&mut body)
+            {
+                return (400, error_body(&err.to_string()));
+            }
+
+            let page = serde_json::from_str::<Value>(&body)
+                .map_err(|err| err.to_string())
+                .and_then(|page| page_from_json(&page));
+            let page = match page {
+                Ok(page) => page,
+                Err(err) => return (400, error_body(&err)),
+            };
+
+            let _writes = state.writes.lock().unwrap();
+            match database.write().unwrap().add_page(page) {
+                Ok(id) => (201, json!({ "id": id })),
+                Err(err) => (422, error_body(&err.0)),
+            }
+        }
+        (Method::Post, "/flush") => match flush(state) {
+            Ok(flushed) => (200, json!({ "flushed": flushed })),
+            Err(err) => (500, error_body(&err.0)),
+        },
+        (_, "/search") | (_, "/pages") | (_, "/flush") => {
+            (405, error_body("method not allowed"))
+        }
+        _ => (404, error_body("not found")),
+    }
+}
+
+/// writes the pages added since the last flush out to disk every `interval`
+fn flush_periodically(state: &State, interval: Duration) {
+    loop {
+        sleep(interval);
+
+        let now = Instant::now();
+        match flush(state) {
+            Ok(0) => {}
+            Ok(flushed) => println!("flushed {} pages in {}", flushed, now.elapsed().readable()),
+            Err(err) => eprintln!("Error: flushing pages failed: {}", err.0),
+        }
+    }
+}
+
+/// swaps in the indices of shards that other processes import into, compact or optimize, while
+/// searches go on with the indices in use until they are loaded
+fn reload_periodically(database: &RwLock<Database>) {
+    loop {
+        sleep(RELOAD_INTERVAL);
+
+        let now = Instant::now();
+        let loaded = database.read().unwrap().load_changed();
+        match loaded {
+            Ok(ref loaded) if loaded.is_empty() => {}
+            Ok(loaded) => {
+                let reloaded = database.write().unwrap().swap_indices(loaded);
+                if reloaded > 0 {
+                    println!("reloaded {} shards in {}", reloaded, now.elapsed().readable());
+                }
+            }
+            // like when stores are removed while they are loaded, they are loaded again next time
+            Err(err) => eprintln!("Error: reloading the indices failed: {}", err.0),
+        }
+    }
+}
+
+impl Database {
+    /// serves searches and additions of single pages over HTTP on `address`. added pages can be
+    /// searched right away, and are flushed to disk every `flush_interval` seconds. stores other
+    /// processes write to the data dir are picked up as they are written. several data dirs are
+    /// served read-only
+    pub fn serve(mut self, address: &str, flush_interval: u64) -> Result<(), StrError> {
+        // duplicates of added pages are skipped, several data dirs served together only search
+        if !self.is_federated() {
+            self.load_digests()?;
+        }
+
+        let server = Server::http(address)
+            .map_err(|err| format!("couldn't listen on {}: {}", address, err))?;
+        let server = Arc::new(server);
+        let state = Arc::new(State {
+            database: RwLock::new(self),
+            writes: Mutex::new(()),
+        });
+
+        {
+            let state = state.clone();
+            let interval = Duration::from_secs(flush_interval.max(1));
+            spawn(move || flush_periodically(&state, interval));
+        }
+        {
+            let state = state.clone();
+            spawn(move || reload_periodically(&state.database));
+        }
+
+        println!("listening on http://{}", address);
+
+        let workers = (0..WORKERS)
+            .map(|_| {
+                let server = server.clone();
+                let state = state.clone();
+                spawn(move || {
+                    while let Ok(mut request) = server.recv() {
+                        let (status, body) = handle(&state, &mut request);
+                        if let Err(err) = request.respond(json_response(status, &body)) {
+                            eprintln!("Error: couldn't send a response: {}", err);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for worker in workers {
+            worker.join().map_err(|_| "a request handler panicked")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::{read_dir, remove_dir, remove_dir_all, rename};
+use std::path::{Path, PathBuf};
+
+use config::Config;
+use database::{shard_dirs, Database};
+use errors::StrError;
+use storage::{check_urls, snapshot_dir};
+
+/// how many times a snapshot is retried when the data dir keeps changing under it
+const SNAPSHOT_ATTEMPTS: usize = 10;
+
+/// the dirs that hold stores, which are the shards of a sharded data dir, paired with where their
+/// copies go under `to`
+fn store_dirs(from: &Path, to: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let shards = shard_dirs(from);
+    if shards.is_empty() {
+        return vec![(from.to_path_buf(), to.to_path_buf())];
+    }
+
+    shards
+        .into_iter()
+        .map(|shard| {
+            let copy = to.join(shard.file_name().unwrap());
+            (shard, copy)
+        })
+        .collect()
+}
+
+/// copies every store dir of `from` into the new dir `to`, followed by the settings of `from`,
+/// or leaves nothing behind
+fn copy_data_dir(from: &Path, to: &Path, attempts: usize) -> Result<(), StrError> {
+    use std::fs::create_dir;
+
+    let dirs = store_dirs(from, to);
+    if dirs[0].0 != from {
+        create_dir(to)?;
+    }
+
+    for (from, to) in dirs {
+        if let Err(err) = snapshot_dir(&from, &to, attempts) {
+            let _ = remove_dir_all(&to);
+            return Err(err);
+        }
+    }
+
+    // the stores are only searched right with the settings they were imported with
+    if let Err(err) = Config::copy(from, to) {
+        let _ = remove_dir_all(to);
+        return Err(err);
+    }
+
+    Ok(())
+}
+
+impl Database {
+    /// makes a point in time copy of a data dir in `dest` while it may be imported into,
+    /// hardlinking the store files, which are never changed once written, and copying the index
+    /// files that list them
+    pub fn snapshot(data_dir: &str, dest: &str) -> Result<(), StrError> {
+        let dest = Path::new(dest);
+        if dest.exists() {
+            Err(format!("{} already exists", dest.display()))?;
+        }
+
+        copy_data_dir(Path::new(data_dir), dest, SNAPSHOT_ATTEMPTS)?;
+        println!("wrote a snapshot of {} to {}", data_dir, dest.display());
+
+        Ok(())
+    }
+
+    /// checks that every store a snapshot lists is there and complete, then installs it as the
+    /// data dir, which must be empty. the snapshot is left as it is
+    pub fn restore(snapshot: &str, data_dir: &str) -> Result<(), StrError> {
+        let snapshot = Path::new(snapshot);
+        let data_dir = Path::new(data_dir);
+        if !snapshot.is_dir() {
+            Err(format!("{} is not a snapshot", snapshot.display()))?;
+        }
+        // a snapshot without its settings would be searched with the defaults, which the
+        // stores may not have been imported with
+        if Config::read(snapshot)
+            .map_err(|err| format!("{} is not a valid snapshot: {}", snapshot.display(), err.0))?
+            .is_none()
+        {
+            Err(format!("{} is not a valid snapshot: it has no xray.toml", snapshot.display()))?;
+        }
+        if data_dir.exists() && read_dir(data_dir)?.next().is_some() {
+            Err(format!("{} isn't empty, restore into a new data dir", data_dir.display()))?;
+        }
+
+        // the copy is made next to the data dir and only moved into place once it is complete
+        let mut partial = data_dir.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let copied = copy_data_dir(snapshot, &partial, 1).and_then(|()| {
+            // a snapshot whose stores refer to pages it doesn't have would fail searches later
+            store_dirs(&partial, &partial)
+                .iter()
+                .try_for_each(|(dir, _)| check_urls(dir))
+        });
+        if let Err(err) = copied {
+            let _ = remove_dir_all(&partial);
+            Err(format!("{} is not a valid snapshot: {}", snapshot.display(), err.0))?;
+        }
+
+        if data_dir.exists() {
+            remove_dir(data_dir)?;
+        }
+        rename(&partial, data_dir)?;
+        println!("restored {} from {}", data_dir.display(), snapshot.display());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use std::ops::Range;
+
+use rayon_hash::HashSet;
+
+use config::Analysis;
+
+/// the number of words shown in a snippet
+const SNIPPET_WORDS: usize = 24;
+
+/// A short excerpt of a page, with the byte ranges of the words that matched the query
+#[derive(Clone, Debug, Default)]
+pub struct Snippet {
+    pub text: String,
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    /// finds the window of text containing the most distinct query terms, preferring more total
+    /// hits and then the earliest window
+    pub fn new(text: &str, terms: &[String], analysis: &Analysis) -> Snippet {
+        let terms = terms
+            .iter()
+            .filter_map(|term| analysis.canonicalize(term))
+            .collect::<HashSet<_>>();
+
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        let matches = words
+            .iter()
+            .map(|word| analysis.canonicalize(word).filter(|word| terms.contains(word)))
+            .collect::<Vec<_>>();
+
+        let window = SNIPPET_WORDS.min(words.len());
+        let mut best = (0, 0, 0); // (distinct terms, hits, start)
+        for start in 0..(words.len() - window + 1) {
+            let hits = matches[start..start + window]
+                .iter()
+                .filter_map(|m| m.as_ref())
+                .collect::<Vec<_>>();
+            let distinct = hits.iter().collect::<HashSet<_>>().len();
+            if (distinct, hits.len()) > (best.0, best.1) {
+                best = (distinct, hits.len(), start);
+            }
+        }
+
+        let start = best.2;
+        let end = start + window;
+
+        let mut snippet = Snippet::default();
+        if start > 0 {
+            snippet.text.push_str("... ");
+        }
+        for idx in start..end {
+            if idx > start {
+                snippet.text.push(' ');
+            }
+            let word_start = snippet.text.len();
+            snippet.text.push_str(words[idx]);
+            if matches[idx].is_some() {
+                snippet.highlights.push(word_start..snippet.text.len());
+            }
+        }
+        if end < words.len() {
+            snippet.text.push_str(" ...");
+        }
+
+        snippet
+    }
+
+    /// renders the snippet with each highlighted word wrapped in `before` and `after`
+    pub fn highlighted(&self, before: &str, after: &str) -> String {
+        let mut output = String::with_capacity(self.text.len());
+        let mut last = 0;
+        for range in &self.highlights {
+            output.push_str(&self.text[last..range.start]);
+            output.push_str(before);
+            output.push_str(&self.text[range.clone()]);
+            output.push_str(after);
+            last = range.end;
+        }
+        output.push_str(&self.text[last..]);
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|term| term.to_string()).collect()
+    }
+
+    #[test]
+    fn highlights_matching_words() {
+        let analysis = Analysis::default();
+        let text = "The Rust compiler, rust, and rust's borrow checker";
+        let snippet = Snippet::new(text, &terms(&["rust"]), &analysis);
+
+        assert_eq!(snippet.text, text);
+        // punctuation is stripped before matching, so "rust's" is a different word
+        assert_eq!(
+            snippet.highlighted("[", "]"),
+            "The [Rust] compiler, [rust,] and rust's borrow checker"
+        );
+    }
+
+    #[test]
+    fn picks_the_window_with_the_most_distinct_terms() {
+        let analysis = Analysis::default();
+        let mut text = vec!["filler"; 30];
+        text.extend(&["rust", "filler", "compiler"]);
+        text.extend(vec!["filler"; 30]);
+        let snippet = Snippet::new(&text.join(" "), &terms(&["rust", "compiler"]), &analysis);
+
+        let highlighted = snippet.highlighted("<", ">");
+        assert!(highlighted.starts_with("... "));
+        assert!(highlighted.ends_with(" ..."));
+        assert!(highlighted.contains("<rust> filler <compiler>"));
+        assert_eq!(snippet.text.split_whitespace().count(), SNIPPET_WORDS + 2);
+    }
+
+    #[test]
+    fn short_texts_are_shown_whole() {
+        let analysis = Analysis::default();
+        let snippet = Snippet::new("no matching words here", &terms(&["rust"]), &analysis);
+        assert_eq!(snippet.highlighted("<", ">"), "no matching words here");
+        assert!(snippet.highlights.is_empty());
+    }
+}
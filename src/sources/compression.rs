@@ -0,0 +1,125 @@
+use std::fs::File;
+use std::io::{stdin, BufReader, Cursor, Read};
+use std::path::Path;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+use errors::StrError;
+
+/// the suffixes of compressed files, which are left out when checking a file's format
+pub const COMPRESSED_EXTENSIONS: [&str; 4] = [".gz", ".zst", ".bz2", ".xz"];
+
+/// the path that stands for stdin in `import`
+pub const STDIN_PATH: &str = "-";
+
+/// the longest magic number that is sniffed
+const MAGIC_LEN: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// the compression the first bytes of a stream start with
+    fn sniff(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if matches!(magic, [b'B', b'Z', b'h', b'1'..=b'9', ..]) {
+            // followed by the block size in hundreds of kB
+            Compression::Bzip2
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Compression::None => "plain",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+            Compression::Xz => "xz",
+        }
+    }
+}
+
+/// reads up to `buf.len()` bytes, fewer only at the end of the stream
+fn read_up_to(reader: &mut dyn Read, buf: &mut [u8]) -> Result<usize, StrError> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..])? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    Ok(len)
+}
+
+/// reads the whole of a file given to `import`, or of stdin for `-`, decompressing it if it
+/// starts with the magic number of gzip, zstd, bzip2 or xz whatever the file is named
+pub fn read_content(source: &Path) -> Result<Vec<u8>, StrError> {
+    let reader: Box<dyn Read> = if source == Path::new(STDIN_PATH) {
+        Box::new(stdin())
+    } else {
+        Box::new(File::open(source)?)
+    };
+    let mut reader = BufReader::new(reader);
+
+    // the sniffed bytes are put back in front of the rest of the stream, which can't be seeked
+    // when it is stdin
+    let mut magic = [0; MAGIC_LEN];
+    let magic_len = read_up_to(&mut reader, &mut magic)?;
+    let compression = Compression::sniff(&magic[..magic_len]);
+    let reader = Cursor::new(&magic[..magic_len]).chain(reader);
+
+    let mut content = Vec::new();
+    let result = match compression {
+        Compression::None => {
+            let mut reader = reader;
+            reader.read_to_end(&mut content)
+        }
+        Compression::Gzip => MultiGzDecoder::new(reader).read_to_end(&mut content),
+        Compression::Zstd => ZstdDecoder::new(reader)?.read_to_end(&mut content),
+        Compression::Bzip2 => MultiBzDecoder::new(reader).read_to_end(&mut content),
+        Compression::Xz => XzDecoder::new_multi_decoder(reader).read_to_end(&mut content),
+    };
+
+    match result {
+        Ok(_) => Ok(content),
+        Err(err) if compression == Compression::None => Err(err)?,
+        Err(_) => Err(format!(
+            "decoding {} stream failed for {}",
+            compression.name(),
+            source.display()
+        ))?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_compression() {
+        assert_eq!(Compression::sniff(&[0x1f, 0x8b, 0x08]).name(), "gzip");
+        assert_eq!(Compression::sniff(b"BZh91AY&SY").name(), "bzip2");
+        assert_eq!(Compression::sniff(b"BZh1").name(), "bzip2");
+        // text that happens to start like bzip2 isn't
+        assert_eq!(Compression::sniff(b"BZh0").name(), "plain");
+        assert_eq!(Compression::sniff(b"BZhello world").name(), "plain");
+        assert_eq!(Compression::sniff(b"BZh").name(), "plain");
+        assert_eq!(Compression::sniff(b"WARC/1.0").name(), "plain");
+    }
+}
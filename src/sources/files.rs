@@ -0,0 +1,78 @@
+use std::fs::metadata;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use errors::StrError;
+use sources::html::{html_text, html_title};
+use sources::{has_extension, RawPage, Record, Source};
+
+/// Directory trees of `.txt`, `.md` and `.html` files, with each file a page that has its path
+/// as its URL and the time it was last modified as its date
+pub struct FilesSource;
+
+/// formats a time as seconds since the Unix epoch as an RFC 3339 UTC date, like WARC dates
+fn format_date(secs: u64) -> String {
+    // days to a civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+impl Source for FilesSource {
+    fn accepts(&self, path: &Path) -> bool {
+        has_extension(path, &[".txt", ".md", ".markdown", ".html", ".htm"])
+    }
+
+    fn read_pages(&self, path: &Path, content: &[u8]) -> Result<Vec<Record>, StrError> {
+        let content = String::from_utf8_lossy(content);
+
+        let (title, text) = if has_extension(path, &[".html", ".htm"]) {
+            (Some(html_title(&content).unwrap_or_default()), html_text(&content))
+        } else if has_extension(path, &[".md", ".markdown"]) {
+            // a leading heading is the title, otherwise the first line is like for text files
+            let first_line = content.lines().next().unwrap_or("");
+            if first_line.starts_with('#') {
+                let title = first_line.trim_start_matches('#').trim().to_string();
+                (Some(title), content.into_owned())
+            } else {
+                (None, content.into_owned())
+            }
+        } else {
+            (None, content.into_owned())
+        };
+
+        // a file read from stdin has no modification time
+        let date = metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| format_date(modified.as_secs()))
+            .unwrap_or_default();
+
+        Ok(vec![Ok(RawPage {
+            url: path.display().to_string(),
+            title,
+            text,
+            date,
+            ..Default::default()
+        })])
+    }
+}
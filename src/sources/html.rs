@@ -0,0 +1,156 @@
+/// elements whose content isn't text shown on the page
+const SKIPPED_ELEMENTS: [&str; 6] = ["script", "style", "noscript", "template", "svg", "head"];
+
+/// elements that start a new line of text
+const BLOCK_ELEMENTS: [&str; 20] = [
+    "address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt", "footer",
+    "h1", "h2", "h3", "h4", "h5", "h6", "header", "li", "p", "tr",
+];
+
+/// the lowercased name of the tag starting at `<`, and whether it is a closing tag
+fn tag_name(tag: &str) -> (String, bool) {
+    let tag = tag.trim_start_matches('<');
+    let (tag, closing) = match tag.strip_prefix('/') {
+        Some(tag) => (tag, true),
+        None => (tag, false),
+    };
+    let name = tag.chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    (name, closing)
+}
+
+/// decodes the character references of the common named entities and numeric ones, leaving
+/// others as they are
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        // entities are short, a `;` further on belongs to something else
+        let end = match rest.char_indices().take(12).find(|&(_, c)| c == ';') {
+            Some((end, _)) => end,
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ if entity.starts_with("#x") || entity.starts_with("#X") => {
+                u32::from_str_radix(&entity[2..], 16).ok().and_then(::std::char::from_u32)
+            }
+            _ if entity.starts_with('#') => {
+                entity[1..].parse().ok().and_then(::std::char::from_u32)
+            }
+            _ => None,
+        };
+
+        match c {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// the text of the `<title>` element, if the page has one
+pub fn html_title(html: &str) -> Option<String> {
+    let lowercase = html.to_ascii_lowercase();
+    let start = lowercase.find("<title")?;
+    let start = start + lowercase[start..].find('>')? + 1;
+    let end = start + lowercase[start..].find("</title")?;
+
+    let title = decode_entities(&html[start..end]);
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+/// the text shown on a page, without its markup, scripts and styles, with block elements on
+/// lines of their own
+pub fn html_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len() / 2);
+    // the element whose content is being skipped
+    let mut skipping: Option<String> = None;
+
+    let mut rest = html;
+    while !rest.is_empty() {
+        let start = match rest.find('<') {
+            Some(start) => start,
+            None => {
+                if skipping.is_none() {
+                    text.push_str(&decode_entities(rest));
+                }
+                break;
+            }
+        };
+        if skipping.is_none() {
+            text.push_str(&decode_entities(&rest[..start]));
+        }
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let (name, closing) = tag_name(&rest[..end]);
+        rest = &rest[end + 1..];
+
+        match skipping {
+            Some(ref skipped) if closing && *skipped == name => {}
+            Some(_) => continue,
+            None if !closing && SKIPPED_ELEMENTS.contains(&name.as_str()) => {
+                skipping = Some(name);
+                continue;
+            }
+            None => {
+                if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    text.push('\n');
+                } else {
+                    text.push(' ');
+                }
+                continue;
+            }
+        }
+        skipping = None;
+    }
+
+    // collapse the whitespace of the markup, keeping one line per block
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+use std::str;
+
+use serde_json::{self, Value};
+
+use errors::StrError;
+use sources::{has_extension, RawPage, Record, Source};
+
+/// JSON Lines files with a `{"url", "title", "text"}` object per page, which can also have a
+/// `date`
+pub struct JsonLinesSource;
+
+/// reads a page from a `{"url", "title", "text"}` object, which can also have a `date`
+pub fn page_from_json(page: &Value) -> Result<RawPage, String> {
+    let field = |name: &str| page.get(name).and_then(Value::as_str).map(str::to_string);
+
+    Ok(RawPage {
+        url: field("url").ok_or("missing \"url\"")?,
+        title: Some(field("title").unwrap_or_default()),
+        text: field("text").ok_or("missing \"text\"")?,
+        date: field("date").unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+impl Source for JsonLinesSource {
+    fn accepts(&self, path: &Path) -> bool {
+        has_extension(path, &[".jsonl", ".ndjson"])
+    }
+
+    fn read_pages(&self, path: &Path, content: &[u8]) -> Result<Vec<Record>, StrError> {
+        let content = str::from_utf8(content)
+            .map_err(|_| format!("{} isn't valid UTF-8", path.display()))?;
+
+        let mut pages = Vec::new();
+        for (line_num, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let error =
+                |message: String| format!("{}:{}: {}", path.display(), line_num + 1, message);
+            let page = serde_json::from_str::<Value>(line)
+                .map_err(|err| err.to_string())
+                .and_then(|page| page_from_json(&page));
+            pages.push(page.map_err(error));
+        }
+
+        Ok(pages)
+    }
+}
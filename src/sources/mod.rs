@@ -0,0 +1,134 @@
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use errors::StrError;
+use sources::compression::{COMPRESSED_EXTENSIONS, STDIN_PATH};
+
+pub mod compression;
+mod files;
+mod html;
+pub mod jsonl;
+mod warc;
+mod wet;
+
+/// A page as read from a source, before its words are indexed
+#[derive(Clone, Debug, Default)]
+pub struct RawPage {
+    pub url: String,
+    /// None when the first line of the text is the title, like in WET files
+    pub title: Option<String>,
+    pub text: String,
+    pub date: String,
+    pub record_id: String,
+    pub refers_to: String,
+    pub block_digest: String,
+}
+
+/// A record read from a source: a page, or why the record couldn't be parsed
+pub type Record = Result<RawPage, String>;
+
+/// A format that pages can be imported from
+pub trait Source: Sync {
+    /// whether a file found in a directory given to `import` is in this format
+    fn accepts(&self, path: &Path) -> bool;
+
+    /// reads the pages out of the (decompressed) content of a file. records that can't be parsed
+    /// are returned as errors and skipped, only a file that can't be read at all is an error
+    fn read_pages(&self, path: &Path, content: &[u8]) -> Result<Vec<Record>, StrError>;
+}
+
+/// The formats `import` can read, chosen with `--format`
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Wet,
+    Warc,
+    JsonLines,
+    Files,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Format, String> {
+        match format {
+            "wet" => Ok(Format::Wet),
+            "warc" => Ok(Format::Warc),
+            "jsonl" => Ok(Format::JsonLines),
+            "files" => Ok(Format::Files),
+            _ => Err(format!(
+                "unknown import format {}, expected wet, warc, jsonl or files",
+                format
+            )),
+        }
+    }
+}
+
+impl Format {
+    pub fn source(self) -> &'static dyn Source {
+        match self {
+            Format::Wet => &wet::WetSource,
+            Format::Warc => &warc::WarcSource,
+            Format::JsonLines => &jsonl::JsonLinesSource,
+            Format::Files => &files::FilesSource,
+        }
+    }
+}
+
+/// the input from the WARC or WET record after the one at its start, which couldn't be parsed
+fn skip_record(input: &[u8]) -> &[u8] {
+    let start = input.iter().position(|b| !b" \r\n\t".contains(b)).unwrap_or(0);
+    let next = input[start..]
+        .windows(6)
+        .skip(1)
+        .position(|window| window == b"\nWARC/");
+    match next {
+        Some(pos) => &input[start + pos + 2..],
+        None => &[],
+    }
+}
+
+/// the files a path given to `import` stands for: the path itself if it is a file, otherwise
+/// every file in the directory tree under it that is in the source's format. `-` stands for stdin
+pub fn path_to_files(path: String, source: &dyn Source) -> Vec<PathBuf> {
+    fn walk(dir: &Path, source: &dyn Source, files: &mut Vec<PathBuf>) {
+        let entries = match read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let entry = entry.path();
+            if entry.is_dir() {
+                walk(&entry, source, files);
+            } else if entry.is_file() && source.accepts(&entry) {
+                files.push(entry);
+            }
+        }
+    }
+
+    let path = PathBuf::from(path);
+    if path.is_file() || path == Path::new(STDIN_PATH) {
+        return vec![path];
+    }
+    if !path.is_dir() {
+        panic!("ERROR: invalid path provided! Path was {}", path.display());
+    }
+
+    let mut files = Vec::new();
+    walk(&path, source, &mut files);
+    files.sort_unstable();
+
+    files
+}
+
+/// whether the file name, without a compression suffix like `.gz`, ends with one of the
+/// extensions
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    let file_name = path.to_str().unwrap_or("");
+    let file_name = COMPRESSED_EXTENSIONS
+        .iter()
+        .find_map(|compressed| file_name.strip_suffix(compressed))
+        .unwrap_or(file_name);
+    extensions.iter().any(|extension| file_name.ends_with(extension))
+}
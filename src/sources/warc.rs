@@ -0,0 +1,173 @@
+use std::path::Path;
+use std::str;
+
+use errors::StrError;
+use sources::html::{html_text, html_title};
+use sources::{has_extension, skip_record, RawPage, Record, Source};
+
+/// WARC files, the raw HTTP responses of a crawl. HTML responses have their text extracted, and
+/// the conversion records of WET files are read as they are
+pub struct WarcSource;
+
+/// the (name, value) header lines of a record
+type Headers<'a> = Vec<(&'a str, &'a str)>;
+
+/// One record of a WARC file
+struct WarcRecord<'a> {
+    headers: Headers<'a>,
+    content: &'a [u8],
+}
+
+impl<'a> WarcRecord<'a> {
+    fn header(&self, name: &str) -> &'a str {
+        self.headers
+            .iter()
+            .find(|&&(header, _)| header.eq_ignore_ascii_case(name))
+            .map_or("", |&(_, value)| value)
+    }
+}
+
+/// splits off the first line, without its line ending
+fn take_line(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = input.iter().position(|&b| b == b'\n')?;
+    let line = &input[..end];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Some((line, &input[end + 1..]))
+}
+
+/// splits `Name: value` header lines up to the first empty line off the input
+fn parse_headers(mut input: &[u8]) -> Result<(Headers<'_>, &[u8]), StrError> {
+    let mut headers = Vec::new();
+    loop {
+        let (line, rest) = take_line(input).ok_or("unexpected end of headers")?;
+        input = rest;
+        if line.is_empty() {
+            return Ok((headers, input));
+        }
+
+        let line = str::from_utf8(line).map_err(|_| "header isn't valid UTF-8")?;
+        if let Some(colon) = line.find(':') {
+            headers.push((line[..colon].trim(), line[colon + 1..].trim()));
+        }
+    }
+}
+
+/// reads the next record, None at the end of the input
+fn next_record(input: &[u8]) -> Result<Option<(WarcRecord<'_>, &[u8])>, StrError> {
+    let start = match input.iter().position(|b| !b" \r\n\t".contains(b)) {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    let (version, rest) = take_line(&input[start..]).ok_or("unexpected end of WARC record")?;
+    if !version.starts_with(b"WARC/") {
+        Err("expected a WARC record")?;
+    }
+
+    let (headers, rest) = parse_headers(rest)?;
+    let record = WarcRecord {
+        headers,
+        content: &[],
+    };
+    let content_length = record
+        .header("Content-Length")
+        .parse::<usize>()
+        .map_err(|_| "WARC record without a valid Content-Length")?;
+    if content_length > rest.len() {
+        Err("WARC record is cut off")?;
+    }
+
+    let record = WarcRecord {
+        content: &rest[..content_length],
+        ..record
+    };
+    Ok(Some((record, &rest[content_length..])))
+}
+
+/// the page in a successful HTTP response, if it is HTML or plain text
+fn response_page(record: &WarcRecord) -> Option<RawPage> {
+    let (status, rest) = take_line(record.content)?;
+    let mut status = str::from_utf8(status).ok()?.split_whitespace();
+    if !status.next()?.starts_with("HTTP/") || status.next() != Some("200") {
+        return None;
+    }
+
+    let (headers, body) = parse_headers(rest).ok()?;
+    let content_type = headers
+        .iter()
+        .find(|&&(header, _)| header.eq_ignore_ascii_case("Content-Type"))
+        .map_or("", |&(_, value)| value)
+        .to_ascii_lowercase();
+
+    let body = String::from_utf8_lossy(body);
+    let (title, text) = if content_type.starts_with("text/html") {
+        (html_title(&body), html_text(&body))
+    } else if content_type.starts_with("text/plain") {
+        (None, body.into_owned())
+    } else {
+        return None;
+    };
+
+    Some(RawPage {
+        // pages without a title keep their first line as text rather than have it taken as one
+        title: Some(title.unwrap_or_default()),
+        text,
+        ..Default::default()
+    })
+}
+
+impl Source for WarcSource {
+    fn accepts(&self, path: &Path) -> bool {
+        has_extension(path, &[".warc"])
+    }
+
+    fn read_pages(&self, path: &Path, content: &[u8]) -> Result<Vec<Record>, StrError> {
+        let mut pages = Vec::new();
+
+        let mut remaining = content;
+        loop {
+            let record = match next_record(remaining) {
+                Ok(Some((record, rest))) => {
+                    remaining = rest;
+                    record
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    // the record is skipped up to where the next one starts
+                    let offset = content.len() - remaining.len();
+                    pages.push(Err(format!("{} at byte {}: {}", path.display(), offset, err.0)));
+                    remaining = skip_record(remaining);
+                    continue;
+                }
+            };
+
+            let page = match record.header("WARC-Type") {
+                "response" => response_page(&record),
+                "conversion" => Some(RawPage {
+                    title: None,
+                    text: String::from_utf8_lossy(record.content).into_owned(),
+                    ..Default::default()
+                }),
+                _ => None,
+            };
+
+            if let Some(page) = page {
+                // the payload digest leaves out the HTTP headers, which differ between fetches
+                // of the same page
+                let digest = match record.header("WARC-Payload-Digest") {
+                    "" => record.header("WARC-Block-Digest"),
+                    digest => digest,
+                };
+                pages.push(Ok(RawPage {
+                    url: record.header("WARC-Target-URI").to_string(),
+                    date: record.header("WARC-Date").to_string(),
+                    record_id: record.header("WARC-Record-ID").to_string(),
+                    refers_to: record.header("WARC-Refers-To").to_string(),
+                    block_digest: digest.to_string(),
+                    ..page
+                }));
+            }
+        }
+
+        Ok(pages)
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use nom;
+
+use commoncrawl::{parse_wet_ref, WetRef};
+use errors::StrError;
+use sources::{has_extension, skip_record, RawPage, Record, Source};
+
+/// CommonCrawl WET files, the plain text extracted from a crawl
+pub struct WetSource;
+
+impl Source for WetSource {
+    fn accepts(&self, path: &Path) -> bool {
+        has_extension(path, &[".wet"])
+    }
+
+    fn read_pages(&self, path: &Path, content: &[u8]) -> Result<Vec<Record>, StrError> {
+        let mut pages = Vec::new();
+
+        let mut remaining: &[u8] = content;
+        while !remaining.is_empty() {
+            let blob = match parse_wet_ref(remaining) {
+                Ok((rest, blob)) => {
+                    remaining = rest;
+                    blob
+                }
+                Err(err) => {
+                    let err = match err {
+                        nom::Err::Incomplete(_) => "WET record is cut off",
+                        _ => "invalid WET record",
+                    };
+                    // the record is skipped up to where the next one starts
+                    let offset = content.len() - remaining.len();
+                    pages.push(Err(format!("{} at byte {}: {}", path.display(), offset, err)));
+                    remaining = skip_record(remaining);
+                    continue;
+                }
+            };
+
+            if let WetRef::Conversion {
+                url,
+                date,
+                record_id,
+                refers_to,
+                block_digest,
+                content,
+                ..
+            } = blob
+            {
+                pages.push(Ok(RawPage {
+                    url: url.to_string(),
+                    title: None,
+                    text: content.to_string(),
+                    date: date.to_string(),
+                    record_id: record_id.to_string(),
+                    refers_to: refers_to.to_string(),
+                    block_digest: block_digest.to_string(),
+                }));
+            }
+        }
+
+        Ok(pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conversion(url: &str, content: &str) -> String {
+        format!(
+            "WARC/1.0\r\nWARC-Type: conversion\r\nWARC-Target-URI: {}\r\n\
+             WARC-Date: 2019-01-01T10:00:00Z\r\nWARC-Record-ID: <urn:uuid:0>\r\n\
+             WARC-Refers-To: <urn:uuid:r0>\r\nWARC-Block-Digest: sha1:0\r\n\
+             Content-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}\r\n\r\n",
+            url,
+            content.len(),
+            content
+        )
+    }
+
+    fn read(content: &str) -> Vec<Record> {
+        WetSource
+            .read_pages(Path::new("test.wet"), content.as_bytes())
+            .unwrap()
+    }
+
+    #[test]
+    fn skips_corrupt_records() {
+        let corrupt = "WARC/1.0\r\nWARC-Type: conversion\r\nWARC-Target-URI: https://b.com/\r\n\
+                       Content-Length: nope\r\n\r\nsome text\r\n\r\n";
+        let content = conversion("https://a.com/", "first page")
+            + corrupt
+            + &conversion("https://c.com/", "third page");
+
+        let records = read(&content);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].as_ref().unwrap().url, "https://a.com/");
+        let offset = conversion("https://a.com/", "first page").len();
+        assert_eq!(
+            records[1].as_ref().unwrap_err(),
+            &format!("test.wet at byte {}: invalid WET record", offset)
+        );
+        assert_eq!(records[2].as_ref().unwrap().text, "third page");
+    }
+
+    #[test]
+    fn reports_truncated_records() {
+        let content = conversion("https://a.com/", "first page")
+            + &conversion("https://b.com/", "second page cut off");
+        let content = &content[..content.len() - 10];
+
+        let records = read(content);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_ref().unwrap().url, "https://a.com/");
+        assert!(records[1].as_ref().unwrap_err().ends_with("WET record is cut off"));
+    }
+}
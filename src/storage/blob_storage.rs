@@ -0,0 +1,250 @@
+use errors::StrError;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use rayon::prelude::*;
+use rayon_hash::HashMap;
+
+
+/// A store of arbitrary byte blobs keyed by URL id, laid out like a `UrlStore`, except that ids
+/// don't need to be contiguous, so the jump table records the id at each jump location
+#[derive(Clone, Debug)]
+pub struct BlobStore {
+    pub file_path: PathBuf,
+    pub first_index: u64,
+    pub last_index: u64,
+    pub content_offset: u64,
+    pub jump_table: Vec<(u64, u64)>,
+}
+
+impl BlobStore {
+    fn load(file_path: PathBuf, first_index: u64, last_index: u64) -> Result<BlobStore, Error> {
+        let mut file = BufReader::new(File::open(&file_path)?);
+
+        let jump_table_len = file.read_u64::<LittleEndian>()?;
+
+        // the stride is only informational, the jump table carries the ids
+        let _jump_stride = file.read_u32::<LittleEndian>()?;
+        let mut jump_table = Vec::with_capacity(jump_table_len as usize);
+        for _ in 0..jump_table_len {
+            let id = file.read_u64::<LittleEndian>()?;
+            jump_table.push((id, file.read_u64::<LittleEndian>()?));
+        }
+        jump_table.shrink_to_fit();
+
+        // length of the jump table + len(jump_stride) + len(jump_table_len)
+        let content_offset = jump_table_len * 16 + 12;
+
+        Ok(BlobStore {
+            file_path,
+            first_index,
+            last_index,
+            content_offset,
+            jump_table,
+        })
+    }
+
+    pub fn get_blobs(&self, ids: &[u64]) -> Result<HashMap<u64, Vec<u8>>, Error> {
+        let mut file = BufReader::new(File::open(&self.file_path)?);
+        file.seek(SeekFrom::Start(self.content_offset))?;
+
+        let mut blobs = HashMap::new();
+
+        // the id of the next record the reader is positioned at, if known
+        let mut next_id = self.first_index;
+        for &id in ids {
+            // jump ahead if there is a jump location between the reader and the wanted id
+            let jump = match self.jump_table.binary_search_by_key(&id, |&(id, _)| id) {
+                Ok(idx) => Some(idx),
+                Err(0) => None,
+                Err(idx) => Some(idx - 1),
+            };
+            if let Some(jump) = jump {
+                let (jump_id, offset) = self.jump_table[jump];
+                if jump_id > next_id {
+                    file.seek(SeekFrom::Start(self.content_offset + offset))?;
+                }
+            }
+
+            loop {
+                let len = match file.read_u32::<LittleEndian>() {
+                    Ok(len) => len,
+                    Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(blobs),
+                    Err(err) => Err(err)?,
+                };
+                let cur_id = file.read_u64::<LittleEndian>()?;
+
+                if cur_id < id {
+                    file.seek(SeekFrom::Current(i64::from(len)))?;
+                    continue;
+                }
+
+                if cur_id > id {
+                    // the id isn't in this store, step back so the next lookup sees this record
+                    file.seek(SeekFrom::Current(-12))?;
+                    next_id = cur_id;
+                    break;
+                }
+
+                let mut blob = vec![0; len as usize];
+                file.read_exact(&mut blob)?;
+                blobs.insert(id, blob);
+                next_id = id + 1;
+                break;
+            }
+        }
+
+        Ok(blobs)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BlobIndex(pub Vec<BlobStore>);
+
+impl BlobIndex {
+    fn load_index(dir: &Path, reader: &mut dyn Read) -> Result<BlobStore, Error> {
+        let first_index = reader.read_u64::<LittleEndian>()?;
+        let last_index = reader.read_u64::<LittleEndian>()?;
+
+        let store_path_len = reader.read_u16::<LittleEndian>()? as usize;
+        let mut store_path_bytes = vec![0; store_path_len];
+        reader.read_exact(&mut store_path_bytes)?;
+        let file_path = dir.join(String::from_utf8(store_path_bytes).unwrap());
+
+        BlobStore::load(file_path, first_index, last_index)
+    }
+
+    /// loads the index of every `{name}_*.xraystore` from `{name}.xraystore`
+    pub fn load(dir: &Path, name: &str) -> Result<BlobIndex, StrError> {
+        let blob_idx_store_path = dir.join(format!("{}.xraystore", name));
+        let mut blob_idx_store = BufReader::new(
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&blob_idx_store_path)
+                .unwrap_or_else(|_| {
+                    panic!(
+                        "Could not open or create {} storage file {}",
+                        name,
+                        blob_idx_store_path.display(),
+                    )
+                }),
+        );
+
+        let mut table_entries = Vec::new();
+        loop {
+            match BlobIndex::load_index(dir, &mut blob_idx_store) {
+                Ok(index) => table_entries.push(index),
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => Err(err)?,
+            }
+        }
+
+        Ok(BlobIndex(table_entries))
+    }
+
+    /// gets the blobs for the given ids, ids without a stored blob are left out
+    pub fn get_blobs(&self, mut ids: Vec<u64>) -> HashMap<u64, Vec<u8>> {
+        ids.sort_unstable();
+
+        let mut blobs = HashMap::new();
+        for store in &self.0 {
+            let elements = ids.iter()
+                .cloned()
+                .filter(|&x| x >= store.first_index && x <= store.last_index)
+                .collect::<Vec<_>>();
+            if !elements.is_empty() {
+                blobs.extend(store.get_blobs(&elements).unwrap());
+            }
+        }
+
+        blobs
+    }
+}
+
+fn build_blob_jump_table(sorted_blobs: &[(&u64, &Vec<u8>)], jump_stride: u32) -> Vec<(u64, u64)> {
+    let mut jump_table = Vec::new();
+    let mut jump_loc = 0u64;
+
+    for (jump_idx, &(&id, blob)) in sorted_blobs.iter().enumerate() {
+        // emit a jump table entry for every `jump_stride` blobs
+        if jump_idx % jump_stride as usize == 0 && jump_idx != 0 {
+            jump_table.push((id, jump_loc));
+        }
+
+        // 12 byte header per blob: 4 bytes for the blob length, 8 bytes for the UID
+        jump_loc += blob.len() as u64 + 12;
+    }
+
+    jump_table
+}
+
+/// writes the blobs out to a new `{name}_{first id}.xraystore` and records it in
+/// `{name}.xraystore`
+pub fn store_blobs(
+    dir: &Path,
+    name: &str,
+    jump_stride: u32,
+    blobs: &HashMap<u64, Vec<u8>>,
+) -> Result<(), StrError> {
+    let mut sortable_blobs = blobs.iter().collect::<Vec<_>>();
+    sortable_blobs.par_sort_unstable_by_key(|v| v.0);
+    if sortable_blobs.is_empty() {
+        return Ok(());
+    }
+
+    let jump_table = build_blob_jump_table(&sortable_blobs, jump_stride);
+
+    let start_idx = *sortable_blobs[0].0;
+    let end_idx = *sortable_blobs[sortable_blobs.len() - 1].0;
+    let blob_store_loc = &format!("{}_{}.xraystore", name, start_idx);
+    let mut blob_store = BufWriter::new(File::create(dir.join(blob_store_loc))?);
+
+    // write out the number of entries in the jump table
+    blob_store.write_u64::<LittleEndian>(jump_table.len() as u64)?;
+
+    // write out the stride length of the jump table
+    blob_store.write_u32::<LittleEndian>(jump_stride)?;
+
+    // write out the jump table
+    for (id, loc) in jump_table {
+        blob_store.write_u64::<LittleEndian>(id)?;
+        blob_store.write_u64::<LittleEndian>(loc)?;
+    }
+
+    // now we need to write out each blob
+    for (&uid, blob) in sortable_blobs {
+        // we write out the blob length first to make it easier to skip through the table
+        blob_store.write_u32::<LittleEndian>(blob.len() as u32)?;
+
+        // then save the unique ID for this blob
+        blob_store.write_u64::<LittleEndian>(uid)?;
+
+        // and finally store the blob itself
+        blob_store.write_all(blob)?;
+    }
+
+    // the store is only added to the index once it is complete, so that the index never refers
+    // to a partially written store
+    blob_store.flush()?;
+    let mut blob_idx_store = BufWriter::new(
+        OpenOptions::new()
+            .append(true)
+            .open(dir.join(format!("{}.xraystore", name)))?,
+    );
+
+    // write out the first and last ids stored in this file
+    blob_idx_store.write_u64::<LittleEndian>(start_idx)?;
+    blob_idx_store.write_u64::<LittleEndian>(end_idx)?;
+
+    // save the file name of this blob store
+    blob_idx_store.write_u16::<LittleEndian>(blob_store_loc.len() as u16)?;
+    blob_idx_store.write_all(blob_store_loc.as_bytes())?;
+    blob_idx_store.flush()?;
+
+    Ok(())
+}
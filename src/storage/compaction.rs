@@ -0,0 +1,137 @@
+use std::fs::{metadata, remove_file};
+use std::time::{Duration, Instant};
+
+use errors::StrError;
+use storage::index_storage::{merge_stores, write_index, IndexedData, IndexedStore};
+use storage::Storage;
+
+/// how many similarly sized stores are merged at a time when compacting after an import
+pub const DEFAULT_FANOUT: usize = 4;
+
+/// the memory used for merge buffers when compacting after an import
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// One merge done while compacting
+pub struct Compaction {
+    pub tag: String,
+    /// the number of stores merged
+    pub stores: usize,
+    pub duration: Duration,
+}
+
+/// stores smaller than this are all in the lowest tier
+const MIN_TIER_SIZE: u64 = 1024 * 1024;
+
+/// the size tier of a store, every tier is `fanout` times larger than the one below it
+fn tier(size: u64, fanout: u64) -> u32 {
+    let mut tier = 0;
+    let mut bound = MIN_TIER_SIZE;
+    while size >= bound {
+        tier += 1;
+        bound = bound.saturating_mul(fanout);
+    }
+    tier
+}
+
+/// picks `fanout` stores of the lowest tier that has that many, if any tier does
+fn pick_stores(
+    stores: &[&IndexedStore],
+    fanout: usize,
+) -> Result<Option<Vec<IndexedStore>>, StrError> {
+    let mut tiers = Vec::new();
+    for store in stores {
+        tiers.push((tier(metadata(&store.file_path)?.len(), fanout as u64), *store));
+    }
+    tiers.sort_by_key(|&(tier, _)| tier);
+
+    Ok(tiers
+        .chunk_by(|a, b| a.0 == b.0)
+        .find(|group| group.len() >= fanout)
+        .map(|group| {
+            group
+                .iter()
+                .take(fanout)
+                .map(|&(_, store)| store.clone())
+                .collect()
+        }))
+}
+
+impl Storage {
+    /// merges similarly sized stores of each tag, `fanout` at a time, until no size tier has
+    /// `fanout` stores left, which keeps the number of stores a query reads logarithmic in the
+    /// size of the index without rewriting all of it like `optimize`. each merge is passed to
+    /// `report` once it is done, returns the number of merges
+    pub fn compact(
+        &mut self,
+        fanout: usize,
+        buffer_size: usize,
+        report: &mut dyn FnMut(Compaction),
+    ) -> Result<usize, StrError> {
+        if fanout < 2 {
+            Err("the compaction fanout must be at least 2")?;
+        }
+
+        // imports persist stores without loading them
+        self.indexed_data = IndexedData::load(&self.data_dir)?;
+
+        let mut merges = 0;
+        for tag in self.tags() {
+            loop {
+                let picked = {
+                    let stores = self.indexed_data
+                        .stores
+                        .iter()
+                        .filter(|store| store.tag == tag)
+                        .collect::<Vec<_>>();
+                    pick_stores(&stores, fanout)?
+                };
+                let picked = match picked {
+                    Some(picked) => picked,
+                    None => break,
+                };
+
+                let now = Instant::now();
+                self.merge_picked(&tag, &picked, buffer_size)?;
+                merges += 1;
+                report(Compaction {
+                    tag: tag.clone(),
+                    stores: picked.len(),
+                    duration: now.elapsed(),
+                });
+            }
+        }
+
+        Ok(merges)
+    }
+
+    /// merges the stores into a new store for `tag`, swaps it in for them in the overall index
+    /// and only then removes them
+    fn merge_picked(
+        &mut self,
+        tag: &str,
+        picked: &[IndexedStore],
+        buffer_size: usize,
+    ) -> Result<(), StrError> {
+        let unique = self.next_unique(tag);
+        let stores = picked.iter().collect::<Vec<_>>();
+        let jump_stride = self.config.jump_stride;
+        let written =
+            merge_stores(&self.data_dir, &stores, tag, unique, u64::MAX, jump_stride, buffer_size)?;
+
+        self.indexed_data
+            .stores
+            .retain(|store| !picked.iter().any(|old| old.file_path == store.file_path));
+        for (file_path, num_entries) in written {
+            self.indexed_data
+                .stores
+                .push(IndexedStore::load(file_path, tag.to_string(), num_entries)?);
+        }
+        write_index(&self.data_dir, &self.indexed_data.stores)?;
+
+        for store in picked {
+            remove_file(&store.file_path)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+use std::io::{Error, Write};
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json;
+
+use dump::{DumpFormat, DumpOptions};
+use storage::index_storage::{IndexedStore, WordStream};
+use storage::url_storage::UrlStore;
+use storage::Storage;
+
+/// the buffer used to read a store while dumping it
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// a string as a TSV field, which can't have tabs or line breaks in it
+fn tsv_field(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+/// A record of a dump, written as a JSON object or as a line of TSV fields in the same order
+trait Record: Serialize {
+    fn tsv_fields(&self) -> Vec<String>;
+}
+
+/// A word of an indexed store and how many ids of its set are dumped
+#[derive(Serialize)]
+struct WordRecord<'a> {
+    store: &'a str,
+    word: String,
+    count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    postings: Option<Vec<u64>>,
+}
+
+impl<'a> Record for WordRecord<'a> {
+    fn tsv_fields(&self) -> Vec<String> {
+        let mut fields = vec![self.store.to_string(), self.word.clone(), self.count.to_string()];
+        if let Some(ref postings) = self.postings {
+            let ids = postings.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+            fields.push(ids.join(","));
+        }
+        fields
+    }
+}
+
+/// An entry of the jump table of an indexed store
+#[derive(Serialize)]
+struct WordJumpRecord<'a> {
+    store: &'a str,
+    word: &'a str,
+    offset: u64,
+}
+
+impl<'a> Record for WordJumpRecord<'a> {
+    fn tsv_fields(&self) -> Vec<String> {
+        vec![self.store.to_string(), self.word.to_string(), self.offset.to_string()]
+    }
+}
+
+/// A URL of a URL store
+#[derive(Serialize)]
+struct UrlRecord<'a> {
+    store: &'a str,
+    id: u64,
+    url: String,
+}
+
+impl<'a> Record for UrlRecord<'a> {
+    fn tsv_fields(&self) -> Vec<String> {
+        vec![self.store.to_string(), self.id.to_string(), self.url.clone()]
+    }
+}
+
+/// An entry of the jump table of a URL store
+#[derive(Serialize)]
+struct UrlJumpRecord<'a> {
+    store: &'a str,
+    id: u64,
+    offset: u64,
+}
+
+impl<'a> Record for UrlJumpRecord<'a> {
+    fn tsv_fields(&self) -> Vec<String> {
+        vec![self.store.to_string(), self.id.to_string(), self.offset.to_string()]
+    }
+}
+
+/// writes out one record as a line
+fn write_record<R: Record>(
+    out: &mut dyn Write,
+    format: DumpFormat,
+    record: &R,
+) -> Result<(), Error> {
+    match format {
+        DumpFormat::JsonLines => {
+            serde_json::to_writer(&mut *out, record)?;
+            writeln!(out)
+        }
+        DumpFormat::Tsv => {
+            let fields = record
+                .tsv_fields()
+                .iter()
+                .map(|field| tsv_field(field))
+                .collect::<Vec<_>>();
+            writeln!(out, "{}", fields.join("\t"))
+        }
+    }
+}
+
+/// the path of a store relative to `root`, as it is shown in dumps
+fn store_name(root: &Path, file_path: &Path) -> String {
+    file_path
+        .strip_prefix(root)
+        .unwrap_or(file_path)
+        .display()
+        .to_string()
+}
+
+impl Storage {
+    /// whether `file_path` is one of the indexed or URL stores of this storage
+    pub fn has_store(&self, file_path: &Path) -> bool {
+        self.indexed_data
+            .stores
+            .iter()
+            .any(|store| store.file_path == file_path) ||
+            self.url_index.0.iter().any(|store| store.file_path == file_path)
+    }
+
+    /// dumps every indexed store for `tag`, or only the one at `file_path`, in the order of the
+    /// overall index
+    pub fn dump_indexed(
+        &self,
+        tag: Option<&str>,
+        file_path: Option<&Path>,
+        root: &Path,
+        options: &DumpOptions,
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        for store in &self.indexed_data.stores {
+            if tag.is_some_and(|tag| store.tag != tag) ||
+                file_path.is_some_and(|file_path| store.file_path != file_path)
+            {
+                continue;
+            }
+
+            if options.jump_table {
+                dump_word_jump_table(store, root, options, out)?;
+            } else {
+                dump_words(store, root, options, out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// dumps every URL store, or only the one at `file_path`
+    pub fn dump_urls(
+        &self,
+        file_path: Option<&Path>,
+        root: &Path,
+        options: &DumpOptions,
+        out: &mut dyn Write,
+    ) -> Result<(), Error> {
+        for store in &self.url_index.0 {
+            let (min_id, max_id) = options.id_range();
+            if file_path.is_some_and(|file_path| store.file_path != file_path) ||
+                store.first_index + store.num_entries <= min_id ||
+                store.first_index > max_id
+            {
+                continue;
+            }
+
+            if options.jump_table {
+                dump_url_jump_table(store, root, options, out)?;
+            } else {
+                dump_url_store(store, root, options, out)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// dumps the words of a store that start with the prefix, with the number of ids in their sets
+/// that are in the id range, and the ids too if postings are asked for
+fn dump_words(
+    store: &IndexedStore,
+    root: &Path,
+    options: &DumpOptions,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let name = store_name(root, &store.file_path);
+    let prefix = options.prefix.as_ref().map_or("", |prefix| prefix.as_str());
+
+    // start from the last jump before the first word that can have the prefix
+    let loc = store
+        .jump_table
+        .iter()
+        .take_while(|(word, _)| word.as_str() <= prefix)
+        .last()
+        .map_or(0, |(_, loc)| *loc);
+    let mut stream = WordStream::open_at(store, loc, 0, READ_BUFFER_SIZE)?;
+
+    // without an id range or postings, sets don't need to be read
+    let read_sets = options.postings || options.has_id_range();
+    let (min_id, max_id) = options.id_range();
+
+    while let Some((word, set_length)) = stream
+        .current()
+        .map(|(word, set_length)| (word.to_string(), set_length))
+    {
+        if word.as_str() > prefix && !word.starts_with(prefix) {
+            break;
+        }
+        if !word.starts_with(prefix) {
+            stream.next_word()?;
+            continue;
+        }
+
+        let mut ids = Vec::new();
+        if read_sets {
+            while let Some(id) = stream.next_id()? {
+                if id > max_id {
+                    break;
+                }
+                if id >= min_id {
+                    ids.push(id);
+                }
+            }
+        }
+        stream.next_word()?;
+
+        let count = if read_sets { ids.len() as u64 } else { set_length };
+        if options.has_id_range() && count == 0 {
+            continue;
+        }
+
+        let record = WordRecord {
+            store: &name,
+            word,
+            count,
+            postings: if options.postings { Some(ids) } else { None },
+        };
+        write_record(out, options.format, &record)?;
+    }
+
+    Ok(())
+}
+
+/// dumps the jump table of an indexed store, the words at which reads can start and their
+/// locations relative to the start of the words
+fn dump_word_jump_table(
+    store: &IndexedStore,
+    root: &Path,
+    options: &DumpOptions,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let name = store_name(root, &store.file_path);
+    let prefix = options.prefix.as_ref().map_or("", |prefix| prefix.as_str());
+
+    for (word, loc) in &store.jump_table {
+        if !word.starts_with(prefix) {
+            continue;
+        }
+
+        let record = WordJumpRecord {
+            store: &name,
+            word,
+            offset: *loc,
+        };
+        write_record(out, options.format, &record)?;
+    }
+
+    Ok(())
+}
+
+/// dumps the (id, URL) pairs of a store that are in the id range and start with the prefix
+fn dump_url_store(
+    store: &UrlStore,
+    root: &Path,
+    options: &DumpOptions,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let name = store_name(root, &store.file_path);
+    let prefix = options.prefix.as_ref().map_or("", |prefix| prefix.as_str());
+    let (min_id, max_id) = options.id_range();
+
+    store.scan(min_id, |id, url| {
+        if id > max_id {
+            return Ok(false);
+        }
+
+        if url.starts_with(prefix) {
+            let record = UrlRecord {
+                store: &name,
+                id,
+                url,
+            };
+            write_record(out, options.format, &record)?;
+        }
+
+        Ok(true)
+    })
+}
+
+/// dumps the jump table of a URL store, the ids at which reads can start and their locations
+/// relative to the start of the URLs
+fn dump_url_jump_table(
+    store: &UrlStore,
+    root: &Path,
+    options: &DumpOptions,
+    out: &mut dyn Write,
+) -> Result<(), Error> {
+    let name = store_name(root, &store.file_path);
+    let (min_id, max_id) = options.id_range();
+
+    for (jump, &loc) in store.jump_table.iter().enumerate() {
+        let id = store.first_index + (jump as u64 + 1) * store.jump_stride;
+        if id < min_id || id > max_id {
+            continue;
+        }
+
+        let record = UrlJumpRecord {
+            store: &name,
+            id,
+            offset: loc,
+        };
+        write_record(out, options.format, &record)?;
+    }
+
+    Ok(())
+}
@@ -5,6 +5,7 @@ use std::fs::{File, OpenOptions};
 use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::str;
+use std::sync::Arc;
 use std::u64;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -12,7 +13,8 @@ use rayon::prelude::*;
 use rayon_hash::{HashMap, HashSet};
 use whatlang::Lang;
 
-use super::JUMP_STRIDE;
+use super::postings::PostingCursor;
+use super::store_reader::StoreReader;
 
 #[derive(Clone, Debug)]
 pub struct IndexedStore {
@@ -22,32 +24,46 @@ pub struct IndexedStore {
     pub num_entries: u64,
     pub jump_table: Vec<(String, u64)>,
     pub jump_stride: u32,
+    /// kept open for as long as the store is loaded, every read goes through it
+    file: Arc<File>,
 }
 
 impl IndexedStore {
-    fn load(file_path: String, tag: String, num_entries: u64) -> Result<IndexedStore, Error> {
-        let file_path: PathBuf = file_path.into();
-        let mut file = BufReader::new(File::open(&file_path)?);
-
-        // ensure that the index and file agree on how many entries exist
-        assert_eq!(num_entries, file.read_u64::<LittleEndian>()?);
+    pub fn load(file_path: PathBuf, tag: String, num_entries: u64) -> Result<IndexedStore, Error> {
+        let file = Arc::new(File::open(&file_path)?);
+        let mut reader = BufReader::new(StoreReader::new(file.clone()));
+
+        // ensure that the index and file agree on how many entries exist, they don't when the
+        // file was replaced after the index was read
+        let stored_entries = reader.read_u64::<LittleEndian>()?;
+        if stored_entries != num_entries {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} has {} words but the index expects {}",
+                    file_path.display(),
+                    stored_entries,
+                    num_entries
+                ),
+            ));
+        }
 
-        let jump_table_len = file.read_u64::<LittleEndian>()?;
-        let jump_stride = file.read_u32::<LittleEndian>()?;
+        let jump_table_len = reader.read_u64::<LittleEndian>()?;
+        let jump_stride = reader.read_u32::<LittleEndian>()?;
 
         let mut jump_table = Vec::with_capacity(jump_table_len as usize);
         for _ in 0..jump_table_len {
-            let word_len = file.read_u8()? as usize;
+            let word_len = reader.read_u8()? as usize;
             let mut word = vec![0; word_len];
-            file.read_exact(&mut word)?;
+            reader.read_exact(&mut word)?;
             jump_table.push((
                 String::from_utf8(word).unwrap(),
-                file.read_u64::<LittleEndian>()?,
+                reader.read_u64::<LittleEndian>()?,
             ));
         }
         jump_table.shrink_to_fit();
 
-        let content_offset = file.seek(SeekFrom::Current(0))?;
+        let content_offset = reader.seek(SeekFrom::Current(0))?;
 
         Ok(IndexedStore {
             file_path,
@@ -56,13 +72,21 @@ impl IndexedStore {
             num_entries,
             jump_table,
             jump_stride,
+            file,
         })
     }
 
-    fn get_word<ReadSeek: Read + Seek>(
+    /// a reader over the store's file from its start
+    pub fn reader(&self) -> StoreReader {
+        StoreReader::new(self.file.clone())
+    }
+
+    /// reads the header of the next word, or with `word` moves forward until it is found, leaving
+    /// the reader positioned at the start of its set. returns the word and the set length
+    fn seek_word<ReadSeek: Read + Seek>(
         reader: &mut ReadSeek,
         word: Option<String>,
-    ) -> Result<Option<(String, Vec<u64>)>, Error> {
+    ) -> Result<Option<(String, u64)>, Error> {
         let cur_word_len = reader.read_u8()? as usize;
         let mut cur_word_bytes = vec![0; cur_word_len];
         reader.read_exact(&mut cur_word_bytes)?;
@@ -86,6 +110,18 @@ impl IndexedStore {
             }
         }
 
+        Ok(Some((cur_word, cur_set_length)))
+    }
+
+    fn get_word<ReadSeek: Read + Seek>(
+        reader: &mut ReadSeek,
+        word: Option<String>,
+    ) -> Result<Option<(String, Vec<u64>)>, Error> {
+        let (cur_word, cur_set_length) = match IndexedStore::seek_word(reader, word)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+
         let mut word_set = Vec::new();
 
         for _ in 0..cur_set_length {
@@ -97,7 +133,7 @@ impl IndexedStore {
 
     pub fn get_words(&self, mut words: Vec<String>) -> Result<Vec<(String, Vec<u64>)>, Error> {
         words.sort_unstable();
-        let mut file = BufReader::new(File::open(&self.file_path).unwrap());
+        let mut file = BufReader::new(self.reader());
 
         file.seek(SeekFrom::Start(self.content_offset))?;
 
@@ -133,75 +169,96 @@ impl IndexedStore {
         Ok(word_sets)
     }
 
-    pub fn get_all_words(&self) -> Result<Vec<(String, Vec<u64>)>, Error> {
-        let mut file = BufReader::new(File::open(&self.file_path).unwrap());
+    /// finds where the set of each word starts in the file and how long it is, without reading it
+    pub fn locate_words(&self, mut words: Vec<String>) -> Result<Vec<(String, u64, u64)>, Error> {
+        words.sort_unstable();
+        let mut file = BufReader::new(self.reader());
+
         file.seek(SeekFrom::Start(self.content_offset))?;
 
-        let mut word_sets = Vec::new();
-        loop {
-            match IndexedStore::get_word(&mut file, None) {
-                Ok(Some((word, set))) => {
-                    let _ = word_sets.push((word, set));
+        let mut locations = Vec::new();
+
+        let mut offsets = self.jump_table.iter().cloned().peekable();
+        let mut next_jump_word = offsets.next().unwrap();
+        for word in words {
+            while word >= next_jump_word.0 {
+                let offset = next_jump_word.1;
+
+                next_jump_word = match offsets.next() {
+                    Some(jump_word) => jump_word,
+                    None => break,
+                };
+
+                // only seek once we are in the right range
+                if word < next_jump_word.0 {
+                    file.seek(SeekFrom::Start(self.content_offset + offset))?;
                 }
-                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(word_sets),
+            }
+
+            match IndexedStore::seek_word(&mut file, Some(word.clone())) {
+                Ok(Some((word, set_length))) => {
+                    let offset = file.seek(SeekFrom::Current(set_length as i64 * 8))?;
+                    locations.push((word, offset - set_length * 8, set_length));
+                }
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(locations),
                 Err(err) => Err(err)?,
                 _ => {}
             }
         }
+
+        Ok(locations)
     }
 
-    pub fn get_subset_of_words(
-        &self,
-        start: u64,
-        len: usize,
-    ) -> Result<Vec<(String, Vec<u64>)>, Error> {
-        let mut file = BufReader::new(File::open(&self.file_path).unwrap());
+    pub fn get_all_words(&self) -> Result<Vec<(String, Vec<u64>)>, Error> {
+        let mut file = BufReader::new(self.reader());
         file.seek(SeekFrom::Start(self.content_offset))?;
 
-        // the first offset is at position 0, skip it.
-        //
-        // the last offset is an indeterminate number of words after the second to last
-        // one so, we can't use it for word counting
-        let offsets = &self.jump_table[1..self.jump_table.len() - 1];
-
-        let mut word_num = 1;
-        let mut last_offset = 0;
-        for offset in offsets {
-            word_num += self.jump_stride as u64;
-            if word_num > start {
-                file.seek(
-                    SeekFrom::Start(self.content_offset + last_offset),
-                )?;
-                break;
-            }
-            last_offset = offset.1;
-        }
         let mut word_sets = Vec::new();
-        while word_sets.len() < len {
+        loop {
             match IndexedStore::get_word(&mut file, None) {
                 Ok(Some((word, set))) => {
-                    word_num += 1;
-                    if word_num > start {
-                        let _ = word_sets.push((word, set));
-                    }
+                    let _ = word_sets.push((word, set));
                 }
                 Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(word_sets),
                 Err(err) => Err(err)?,
                 _ => {}
             }
         }
-        return Ok(word_sets);
+    }
+
+    /// reads every word in the store, skipping over the sets
+    pub fn get_all_keys(&self) -> Result<Vec<String>, Error> {
+        let mut file = BufReader::new(self.reader());
+        file.seek(SeekFrom::Start(self.content_offset))?;
+
+        let mut keys = Vec::new();
+        loop {
+            let word_len = match file.read_u8() {
+                Ok(word_len) => word_len as usize,
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(keys),
+                Err(err) => Err(err)?,
+            };
+            let mut word = vec![0; word_len];
+            file.read_exact(&mut word)?;
+            let set_length = file.read_u64::<LittleEndian>()?;
+            file.seek(SeekFrom::Current(set_length as i64 * 8))?;
+            keys.push(String::from_utf8(word).unwrap());
+        }
     }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct IndexedData {
     pub langs: HashMap<String, HashSet<u64>>,
+    /// every `YYYY-MM-DD` day that has pages in the `by_date` stores, sorted
+    pub dates: Vec<String>,
+    /// every host that has pages in the `by_host` stores, sorted
+    pub hosts: Vec<String>,
     pub stores: Vec<IndexedStore>,
 }
 
 impl IndexedData {
-    fn load_index(reader: &mut Read) -> Result<(String, String, u64), Error> {
+    fn load_index(dir: &Path, reader: &mut Read) -> Result<(PathBuf, String, u64), Error> {
         let tag_len = reader.read_u8()? as usize;
         let mut tag = vec![0; tag_len];
         reader.read_exact(&mut tag)?;
@@ -211,13 +268,13 @@ impl IndexedData {
         let store_path_len = reader.read_u16::<LittleEndian>()? as usize;
         let mut store_path_bytes = vec![0; store_path_len];
         reader.read_exact(&mut store_path_bytes)?;
-        let file_path = String::from_utf8(store_path_bytes).unwrap();
+        let file_path = dir.join(String::from_utf8(store_path_bytes).unwrap());
 
         Ok((file_path, String::from_utf8(tag).unwrap(), num_entries))
     }
 
-    pub fn load() -> Result<IndexedData, StrError> {
-        let indexed_idx_store_path = "indexed.xraystore";
+    pub fn load(dir: &Path) -> Result<IndexedData, StrError> {
+        let indexed_idx_store_path = dir.join("indexed.xraystore");
         let mut indexed_idx_store = BufReader::new(
             OpenOptions::new()
                 .read(true)
@@ -226,13 +283,13 @@ impl IndexedData {
                 .open(&indexed_idx_store_path)
                 .expect(&format!(
                     "Could not open or create URL storage file {}",
-                    indexed_idx_store_path,
+                    indexed_idx_store_path.display(),
                 )),
         );
 
         let mut indexed_files = Vec::new();
         loop {
-            match IndexedData::load_index(&mut indexed_idx_store) {
+            match IndexedData::load_index(dir, &mut indexed_idx_store) {
                 Ok(index) => indexed_files.push(index),
                 Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
                 Err(err) => Err(err)?,
@@ -256,13 +313,7 @@ impl IndexedData {
 
             let mut result = HashMap::new();
             for store in lang_store {
-                let temp = store
-                    .get_words(vec![
-                        "eng".to_string(),
-                        "spa".to_string(),
-                        "fra".to_string(),
-                    ])
-                    .unwrap();
+                let temp = store.get_all_words().unwrap();
                 for (lang, set) in temp {
                     result.entry(lang).or_insert_with(HashSet::new).extend(set)
                 }
@@ -273,8 +324,24 @@ impl IndexedData {
             result
         };
 
+        let mut dates = Vec::new();
+        for store in table_entries.iter().filter(|x| x.tag == "by_date") {
+            dates.extend(store.get_all_keys()?);
+        }
+        dates.sort_unstable();
+        dates.dedup();
+
+        let mut hosts = Vec::new();
+        for store in table_entries.iter().filter(|x| x.tag == "by_host") {
+            hosts.extend(store.get_all_keys()?);
+        }
+        hosts.sort_unstable();
+        hosts.dedup();
+
         Ok(IndexedData {
             langs: lang_map,
+            dates,
+            hosts,
             stores: table_entries,
         })
     }
@@ -288,8 +355,8 @@ impl IndexedData {
                 .iter()
                 .cloned()
                 .filter(|x| {
-                    &store.jump_table[0].0 < x &&
-                        &store.jump_table[store.jump_table.len() - 1].0 > x
+                    &store.jump_table[0].0 <= x &&
+                        &store.jump_table[store.jump_table.len() - 1].0 >= x
                 })
                 .collect::<Vec<_>>();
             let elements_map = store.get_words(elements).unwrap();
@@ -304,9 +371,40 @@ impl IndexedData {
 
         word_map
     }
+
+    /// opens a cursor over each word's set in every store for `tag` that has it
+    pub fn get_cursors(
+        &self,
+        tag: &str,
+        words: &[String],
+    ) -> Result<HashMap<String, Vec<PostingCursor>>, Error> {
+        let mut cursors = HashMap::new();
+        for store in self.stores.iter().filter(|store| store.tag == tag) {
+            let elements = words
+                .iter()
+                .filter(|x| {
+                    &store.jump_table[0].0 <= *x &&
+                        &store.jump_table[store.jump_table.len() - 1].0 >= *x
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            for (word, offset, len) in store.locate_words(elements)? {
+                cursors
+                    .entry(word)
+                    .or_insert_with(Vec::new)
+                    .push(PostingCursor::open(store.reader(), offset, len)?);
+            }
+        }
+
+        Ok(cursors)
+    }
 }
 
-fn build_indexed_jump_table(sorted_words: &Vec<(String, Vec<u64>)>) -> Vec<(String, u64)> {
+fn build_indexed_jump_table(
+    sorted_words: &Vec<(String, Vec<u64>)>,
+    jump_stride: u32,
+) -> Vec<(String, u64)> {
     // ensure that the jump table will always have at least one entry
     let mut jump_table = Vec::new();
     let mut jump_loc = 0u64;
@@ -316,8 +414,8 @@ fn build_indexed_jump_table(sorted_words: &Vec<(String, Vec<u64>)>) -> Vec<(Stri
     let mut last_loc = 0;
 
     for &(ref word, ref ids) in sorted_words {
-        // emit a jump table entry for every JUMP_STRIDE words
-        if jump_idx % JUMP_STRIDE == 0 {
+        // emit a jump table entry for every `jump_stride` words
+        if jump_idx % jump_stride == 0 {
             jump_table.push((word.to_string(), jump_loc));
         }
 
@@ -334,29 +432,94 @@ fn build_indexed_jump_table(sorted_words: &Vec<(String, Vec<u64>)>) -> Vec<(Stri
     jump_table
 }
 
-pub fn append_index(indexed_store_loc: &str, tag: &str, num_entries: u64) -> Result<(), Error> {
-    let mut indexed_idx_store =
-        BufWriter::new(OpenOptions::new().append(true).open("indexed.xraystore")?);
+fn write_header<W: Write>(
+    writer: &mut W,
+    num_entries: u64,
+    jump_stride: u32,
+    jump_table: &[(String, u64)],
+) -> Result<(), Error> {
+    // write out how many words are in this file
+    writer.write_u64::<LittleEndian>(num_entries)?;
+
+    // write out the number of entries in the jump table
+    writer.write_u64::<LittleEndian>(jump_table.len() as u64)?;
+    writer.write_u32::<LittleEndian>(jump_stride)?;
 
+    // write out the jump table
+    for (word, loc) in jump_table {
+        let word = word.as_bytes();
+        writer.write_u8(word.len() as u8)?;
+        writer.write_all(word)?;
+        writer.write_u64::<LittleEndian>(*loc)?;
+    }
+
+    Ok(())
+}
+
+fn write_index_entry<W: Write>(
+    writer: &mut W,
+    indexed_store_loc: &str,
+    tag: &str,
+    num_entries: u64,
+) -> Result<(), Error> {
     // write out the tag for the indexed store in overall index first
-    indexed_idx_store.write_u8(tag.len() as u8)?;
-    indexed_idx_store.write(tag.as_bytes())?;
+    writer.write_u8(tag.len() as u8)?;
+    writer.write_all(tag.as_bytes())?;
 
     // write out how many words are in this file
-    indexed_idx_store.write_u64::<LittleEndian>(num_entries)?;
+    writer.write_u64::<LittleEndian>(num_entries)?;
 
     // save the file name of this URL store
-    indexed_idx_store.write_u16::<LittleEndian>(
-        indexed_store_loc.len() as u16,
-    )?;
-    indexed_idx_store.write(indexed_store_loc.as_bytes())?;
+    writer.write_u16::<LittleEndian>(indexed_store_loc.len() as u16)?;
+    writer.write_all(indexed_store_loc.as_bytes())?;
 
     Ok(())
 }
 
+pub fn append_index(
+    dir: &Path,
+    indexed_store_loc: &str,
+    tag: &str,
+    num_entries: u64,
+) -> Result<(), Error> {
+    let mut indexed_idx_store = BufWriter::new(
+        OpenOptions::new()
+            .append(true)
+            .open(dir.join("indexed.xraystore"))?,
+    );
+
+    write_index_entry(&mut indexed_idx_store, indexed_store_loc, tag, num_entries)
+}
+
+/// replaces the overall index with one listing exactly `stores`. it is written next to the old
+/// one and renamed over it, so that a crash leaves either the old or the new index
+pub fn write_index(dir: &Path, stores: &[IndexedStore]) -> Result<(), Error> {
+    use std::fs::rename;
+
+    let partial_path = dir.join("indexed.xraystore.partial");
+    {
+        let mut indexed_idx_store = BufWriter::new(File::create(&partial_path)?);
+        for store in stores {
+            // stores in the data dir are recorded relative to it
+            let file_path = store.file_path.strip_prefix(dir).unwrap_or(&store.file_path);
+            write_index_entry(
+                &mut indexed_idx_store,
+                file_path.to_str().unwrap(),
+                &store.tag,
+                store.num_entries,
+            )?;
+        }
+        indexed_idx_store.flush()?;
+    }
+
+    rename(partial_path, dir.join("indexed.xraystore"))
+}
+
 pub fn store_indexed(
+    dir: &Path,
     tag: &str,
     unique: u64,
+    jump_stride: u32,
     mut indexed_data: Vec<(String, Vec<u64>)>,
 ) -> Result<(), StrError> {
     if indexed_data.is_empty() {
@@ -365,33 +528,13 @@ pub fn store_indexed(
 
     indexed_data.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
 
-    let jump_table = build_indexed_jump_table(&indexed_data);
+    let jump_table = build_indexed_jump_table(&indexed_data, jump_stride);
 
     let indexed_store_loc = &format!("indexed_{}_{}.xraystore", tag, unique);
-    let mut indexed_store = BufWriter::new(File::create(indexed_store_loc)?);
+    let mut indexed_store = BufWriter::new(File::create(dir.join(indexed_store_loc))?);
 
-    if !tag.contains("_tmp") {
-        append_index(indexed_store_loc, tag, indexed_data.len() as u64)?;
-    }
-
-    // write out how many words are in this file
-    indexed_store.write_u64::<LittleEndian>(
-        indexed_data.len() as u64,
-    )?;
-
-    // write out the number of entries in the jump table
-    indexed_store.write_u64::<LittleEndian>(
-        jump_table.len() as u64,
-    )?;
-    indexed_store.write_u32::<LittleEndian>(JUMP_STRIDE)?;
-
-    // write out the jump table
-    for (word, loc) in jump_table {
-        let word = word.as_bytes();
-        indexed_store.write_u8(word.len() as u8)?;
-        indexed_store.write(word)?;
-        indexed_store.write_u64::<LittleEndian>(loc)?;
-    }
+    let num_entries = indexed_data.len() as u64;
+    write_header(&mut indexed_store, num_entries, jump_stride, &jump_table)?;
 
     // now we need to write out each word
     for (word, url_ids) in indexed_data {
@@ -411,5 +554,365 @@ pub fn store_indexed(
         }
     }
 
+    // the store is only added to the overall index once it is complete, so that the index never
+    // refers to a partially written store
+    indexed_store.flush()?;
+    if !tag.contains("_tmp") {
+        append_index(dir, indexed_store_loc, tag, num_entries)?;
+    }
+
     Ok(())
 }
+
+/// Reads a store's words in order without holding their sets, which are read one id at a time
+pub struct WordStream {
+    reader: BufReader<StoreReader>,
+    /// the word the stream is at and how many of its ids are left to read
+    word: Option<(String, u64)>,
+    /// added to every id read
+    id_offset: u64,
+    /// the length of the current word's set
+    set_length: u64,
+}
+
+impl WordStream {
+    pub fn open(
+        store: &IndexedStore,
+        id_offset: u64,
+        buffer_size: usize,
+    ) -> Result<WordStream, Error> {
+        WordStream::open_at(store, 0, id_offset, buffer_size)
+    }
+
+    /// opens the stream at the word starting `loc` bytes into the store's words, like a location
+    /// from its jump table
+    pub fn open_at(
+        store: &IndexedStore,
+        loc: u64,
+        id_offset: u64,
+        buffer_size: usize,
+    ) -> Result<WordStream, Error> {
+        let mut reader = BufReader::with_capacity(buffer_size, store.reader());
+        reader.seek(SeekFrom::Start(store.content_offset + loc))?;
+
+        let mut stream = WordStream {
+            reader,
+            word: None,
+            id_offset,
+            set_length: 0,
+        };
+        stream.next_word()?;
+
+        Ok(stream)
+    }
+
+    /// the word the stream is at and the length of its set
+    pub fn current(&self) -> Option<(&str, u64)> {
+        self.word
+            .as_ref()
+            .map(|(word, _)| (word.as_str(), self.set_length))
+    }
+
+    /// moves to the next word, skipping whatever is left of the current word's set
+    pub fn next_word(&mut self) -> Result<(), Error> {
+        if let Some((_, remaining)) = self.word {
+            self.reader.seek(SeekFrom::Current(remaining as i64 * 8))?;
+        }
+
+        self.word = match self.reader.read_u8() {
+            Ok(word_len) => {
+                let mut word = vec![0; word_len as usize];
+                self.reader.read_exact(&mut word)?;
+                let set_length = self.reader.read_u64::<LittleEndian>()?;
+                self.set_length = set_length;
+                Some((String::from_utf8(word).unwrap(), set_length))
+            }
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => return Err(err),
+        };
+
+        Ok(())
+    }
+
+    pub fn next_id(&mut self) -> Result<Option<u64>, Error> {
+        match self.word {
+            Some((_, ref mut remaining)) if *remaining > 0 => {
+                *remaining -= 1;
+                Ok(Some(self.reader.read_u64::<LittleEndian>()? + self.id_offset))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Writes a store one word at a time. The sets go to a `.partial` file first, since the header
+/// in front of them can only be written once every word is known
+struct IndexedWriter {
+    file_path: PathBuf,
+    partial_path: PathBuf,
+    content: BufWriter<File>,
+    buffer_size: usize,
+    num_entries: u64,
+    jump_stride: u32,
+    jump_table: Vec<(String, u64)>,
+    last_word: (String, u64),
+    loc: u64,
+}
+
+impl IndexedWriter {
+    fn create(
+        dir: &Path,
+        tag: &str,
+        unique: u64,
+        jump_stride: u32,
+        buffer_size: usize,
+    ) -> Result<IndexedWriter, Error> {
+        let file_path = dir.join(format!("indexed_{}_{}.xraystore", tag, unique));
+        let partial_path = dir.join(format!("indexed_{}_{}.xraystore.partial", tag, unique));
+        let content = BufWriter::with_capacity(buffer_size, File::create(&partial_path)?);
+
+        Ok(IndexedWriter {
+            file_path,
+            partial_path,
+            content,
+            buffer_size,
+            num_entries: 0,
+            jump_stride,
+            jump_table: Vec::new(),
+            last_word: (String::new(), 0),
+            loc: 0,
+        })
+    }
+
+    /// writes a word's header, which must be followed by exactly `set_length` ids
+    fn start_word(&mut self, word: &str, set_length: u64) -> Result<(), Error> {
+        assert!(word.len() <= 255);
+
+        // emit a jump table entry for every `jump_stride` words
+        if self.num_entries.is_multiple_of(self.jump_stride as u64) {
+            self.jump_table.push((word.to_string(), self.loc));
+        }
+        self.last_word = (word.to_string(), self.loc);
+
+        self.content.write_u8(word.len() as u8)?;
+        self.content.write_all(word.as_bytes())?;
+        self.content.write_u64::<LittleEndian>(set_length)?;
+
+        self.num_entries += 1;
+        // 9 byte header per word: 1 byte for word length + 8 bytes for the set length
+        self.loc += word.len() as u64 + set_length * 8 + 9;
+
+        Ok(())
+    }
+
+    fn write_id(&mut self, id: u64) -> Result<(), Error> {
+        self.content.write_u64::<LittleEndian>(id)
+    }
+
+    /// writes the header followed by the sets into the store file, returning its path and the
+    /// number of words in it
+    fn finish(mut self) -> Result<(PathBuf, u64), Error> {
+        use std::fs::remove_file;
+        use std::io::copy;
+
+        self.content.flush()?;
+        drop(self.content);
+
+        // always ensure the last word in the index is in the jump table
+        self.jump_table.push(self.last_word);
+
+        let mut store = BufWriter::with_capacity(self.buffer_size, File::create(&self.file_path)?);
+        write_header(&mut store, self.num_entries, self.jump_stride, &self.jump_table)?;
+        copy(&mut File::open(&self.partial_path)?, &mut store)?;
+        store.flush()?;
+
+        remove_file(&self.partial_path)?;
+
+        Ok((self.file_path, self.num_entries))
+    }
+}
+
+/// Merges the stores into new `{tag}` stores in `dir` of at most `words_per_store` words each,
+/// numbered from `first_unique` and with a jump table entry every `jump_stride` words, reading
+/// every store as a sorted stream of words and merging the sets of words they share as they are
+/// written, so that only the read and write buffers, `buffer_size` bytes in total, are held in
+/// memory rather than whole sets.
+/// returns the path and number of words of each store written
+pub fn merge_stores(
+    dir: &Path,
+    stores: &[&IndexedStore],
+    tag: &str,
+    first_unique: u64,
+    words_per_store: u64,
+    jump_stride: u32,
+    buffer_size: usize,
+) -> Result<Vec<(PathBuf, u64)>, Error> {
+    let stores = stores.iter().map(|&store| (store, 0)).collect::<Vec<_>>();
+    merge_renumbered_stores(
+        dir,
+        &stores,
+        tag,
+        first_unique,
+        words_per_store,
+        jump_stride,
+        buffer_size,
+    )
+}
+
+/// like `merge_stores`, but adds the offset paired with each store to the ids read from it, so
+/// that stores numbering their pages from 0 can be merged into one index
+pub fn merge_renumbered_stores(
+    dir: &Path,
+    stores: &[(&IndexedStore, u64)],
+    tag: &str,
+    first_unique: u64,
+    words_per_store: u64,
+    jump_stride: u32,
+    buffer_size: usize,
+) -> Result<Vec<(PathBuf, u64)>, Error> {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    // half of the memory goes to the writer, the other half is split between the readers
+    let read_buffer_size = (buffer_size / 2 / stores.len().max(1)).max(8 * 1024);
+    let write_buffer_size = (buffer_size / 2).max(8 * 1024);
+
+    let mut streams = Vec::with_capacity(stores.len());
+    for &(store, id_offset) in stores {
+        streams.push(WordStream::open(store, id_offset, read_buffer_size)?);
+    }
+
+    let mut words = BinaryHeap::new();
+    for (i, stream) in streams.iter().enumerate() {
+        if let Some((ref word, _)) = stream.word {
+            words.push(Reverse((word.clone(), i)));
+        }
+    }
+
+    let mut written = Vec::new();
+    let mut writer: Option<IndexedWriter> = None;
+
+    while let Some(Reverse((word, first))) = words.pop() {
+        // every stream that is at this word
+        let mut sources = vec![first];
+        while let Some(&Reverse((ref next, i))) = words.peek() {
+            if next != &word {
+                break;
+            }
+            sources.push(i);
+            words.pop();
+        }
+
+        if writer.as_ref().is_some_and(|writer| writer.num_entries >= words_per_store) {
+            written.push(writer.take().unwrap().finish()?);
+        }
+        if writer.is_none() {
+            let unique = first_unique + written.len() as u64;
+            let created = IndexedWriter::create(dir, tag, unique, jump_stride, write_buffer_size)?;
+            writer = Some(created);
+        }
+        let writer = writer.as_mut().unwrap();
+
+        let set_length = sources
+            .iter()
+            .map(|&i| streams[i].word.as_ref().map_or(0, |word| word.1))
+            .sum();
+        writer.start_word(&word, set_length)?;
+
+        // merge the sorted sets id by id
+        let mut ids = BinaryHeap::new();
+        for &i in &sources {
+            if let Some(id) = streams[i].next_id()? {
+                ids.push(Reverse((id, i)));
+            }
+        }
+        while let Some(Reverse((id, i))) = ids.pop() {
+            writer.write_id(id)?;
+            if let Some(id) = streams[i].next_id()? {
+                ids.push(Reverse((id, i)));
+            }
+        }
+
+        for i in sources {
+            streams[i].next_word()?;
+            if let Some((ref word, _)) = streams[i].word {
+                words.push(Reverse((word.clone(), i)));
+            }
+        }
+    }
+
+    if let Some(writer) = writer {
+        written.push(writer.finish()?);
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs::{create_dir, remove_dir_all};
+    use std::process;
+
+    use super::*;
+
+    fn store(dir: &Path, unique: u64, words: &[(&str, Vec<u64>)]) -> IndexedStore {
+        let data = words
+            .iter()
+            .map(|(word, ids)| (word.to_string(), ids.clone()))
+            .collect();
+        store_indexed(dir, "by_word", unique, 2, data).unwrap();
+
+        let path = dir.join(format!("indexed_by_word_{}.xraystore", unique));
+        IndexedStore::load(path, "by_word".to_string(), words.len() as u64).unwrap()
+    }
+
+    #[test]
+    fn merge_renumbers_and_splits_stores() {
+        let dir = ::std::env::temp_dir().join(format!("xray_merge_{}", process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir(&dir).unwrap();
+        // the stores are added to the overall index as they are written
+        File::create(dir.join("indexed.xraystore")).unwrap();
+
+        let first = store(
+            &dir,
+            0,
+            &[("apple", vec![0, 4]), ("cherry", vec![1]), ("kiwi", vec![2, 3])],
+        );
+        let second = store(
+            &dir,
+            1,
+            &[("apple", vec![1, 2]), ("banana", vec![0]), ("kiwi", vec![0]), ("plum", vec![3])],
+        );
+        let third = store(&dir, 2, &[("apple", vec![0]), ("zucchini", vec![1])]);
+
+        // the second and third data dirs' pages come after the first one's five and the second
+        // one's four
+        let offsets = [(&first, 0), (&second, 5), (&third, 9)];
+        let written = merge_renumbered_stores(&dir, &offsets, "merged", 10, 2, 1, 1024).unwrap();
+
+        let mut expected = BTreeMap::new();
+        for &(store, offset) in &offsets {
+            for (word, ids) in store.get_all_words().unwrap() {
+                let merged = expected.entry(word).or_insert_with(Vec::new);
+                merged.extend(ids.into_iter().map(|id| id + offset));
+            }
+        }
+
+        // at most two words go in each store, numbered from 10 and in word order
+        assert_eq!(written.len(), 3);
+        let mut merged = Vec::new();
+        for (i, (path, num_entries)) in written.into_iter().enumerate() {
+            assert_eq!(path, dir.join(format!("indexed_merged_{}.xraystore", 10 + i)));
+            assert!(num_entries <= 2);
+            let store = IndexedStore::load(path, "merged".to_string(), num_entries).unwrap();
+            merged.extend(store.get_all_words().unwrap());
+        }
+
+        assert_eq!(merged, expected.into_iter().collect::<Vec<_>>());
+        assert_eq!(merged[0], ("apple".to_string(), vec![0, 4, 6, 7, 9]));
+
+        remove_dir_all(&dir).unwrap();
+    }
+}
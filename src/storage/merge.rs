@@ -0,0 +1,156 @@
+use std::time::Instant;
+
+use rayon_hash::HashMap;
+
+use errors::StrError;
+use helpers::ReadableDuration;
+use storage::index_storage::{merge_renumbered_stores, write_index, IndexedData, IndexedStore};
+use storage::{blob_storage, index_storage, url_storage, Storage};
+
+impl Storage {
+    /// whether no pages have been imported into this storage
+    pub fn is_empty(&self) -> bool {
+        self.num_pages == 0 && self.indexed_data.stores.is_empty()
+    }
+
+    /// copies every page of `sources` into this storage, numbering the pages of each source after
+    /// the ones before it, and merges the stores of each tag across the sources into stores of at
+    /// most `chunk_size` words. this storage has to be empty
+    pub fn merge_from(
+        &mut self,
+        sources: &[Storage],
+        chunk_size: usize,
+        buffer_size: usize,
+    ) -> Result<(), StrError> {
+        if !self.is_empty() {
+            Err(format!("{} already has pages in it", self.data_dir.display()))?;
+        }
+
+        let mut offsets = Vec::with_capacity(sources.len());
+        let mut num_pages = 0;
+        for source in sources {
+            offsets.push(num_pages);
+            num_pages += source.num_pages;
+        }
+
+        for (source, &offset) in sources.iter().zip(&offsets) {
+            let now = Instant::now();
+            self.copy_pages(source, offset)?;
+            println!(
+                "copied {} pages from {} in {}",
+                source.num_pages,
+                source.data_dir.display(),
+                now.elapsed().readable()
+            );
+        }
+        self.num_pages = num_pages;
+
+        let mut tags = sources
+            .iter()
+            .flat_map(|source| source.tags())
+            .collect::<Vec<_>>();
+        tags.sort_unstable();
+        tags.dedup();
+
+        let mut written = Vec::new();
+        for tag in tags {
+            println!("merging {}", tag);
+            let stores = sources
+                .iter()
+                .zip(&offsets)
+                .flat_map(|(source, &offset)| {
+                    source
+                        .indexed_data
+                        .stores
+                        .iter()
+                        .filter(|store| store.tag == tag)
+                        .map(move |store| (store, offset))
+                })
+                .collect::<Vec<_>>();
+
+            if tag == "by_alternate" {
+                written.extend(self.merge_alternates(&stores)?);
+                continue;
+            }
+
+            let merged = merge_renumbered_stores(
+                &self.data_dir,
+                &stores,
+                &tag,
+                0,
+                chunk_size as u64,
+                self.config.jump_stride,
+                buffer_size,
+            )?;
+            for (file_path, num_entries) in merged {
+                written.push(IndexedStore::load(file_path, tag.clone(), num_entries)?);
+            }
+        }
+
+        write_index(&self.data_dir, &written)?;
+        self.indexed_data = IndexedData::load(&self.data_dir)?;
+
+        Ok(())
+    }
+
+    /// copies the URLs, texts and metadata of every page of `source`, adding `offset` to the ids
+    fn copy_pages(&mut self, source: &Storage, offset: u64) -> Result<(), StrError> {
+        for store in &source.url_index.0 {
+            let ids = (store.first_index..store.first_index + store.num_entries).collect();
+            let urls = source
+                .get_urls(ids)
+                .into_iter()
+                .map(|(id, url)| (id + offset, url))
+                .collect::<HashMap<_, _>>();
+            url_storage::store_urls(&self.data_dir, self.config.jump_stride, &urls)?;
+        }
+
+        for &(name, index) in &[("docs", &source.doc_index), ("meta", &source.meta_index)] {
+            for store in &index.0 {
+                let ids = (store.first_index..store.last_index + 1).collect();
+                let blobs = index
+                    .get_blobs(ids)
+                    .into_iter()
+                    .map(|(id, blob)| (id + offset, blob))
+                    .collect::<HashMap<_, _>>();
+                blob_storage::store_blobs(&self.data_dir, name, self.config.jump_stride, &blobs)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the `by_alternate` stores are keyed by page id, so renumbering them changes the order of
+    /// their words, they are read whole and written out again instead of streamed
+    fn merge_alternates(
+        &self,
+        stores: &[(&IndexedStore, u64)],
+    ) -> Result<Vec<IndexedStore>, StrError> {
+        let mut alternates: HashMap<String, Vec<u64>> = HashMap::new();
+        for &(store, offset) in stores {
+            for (canonical_id, ids) in store.get_all_words()? {
+                let canonical_id = canonical_id.parse::<u64>().unwrap() + offset;
+                alternates
+                    .entry(canonical_id.to_string())
+                    .or_default()
+                    .extend(ids.into_iter().map(|id| id + offset));
+            }
+        }
+        if alternates.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // the overall index is written again once every tag is merged
+        let num_entries = alternates.len() as u64;
+        index_storage::store_indexed(
+            &self.data_dir,
+            "by_alternate",
+            0,
+            self.config.jump_stride,
+            alternates.into_iter().collect(),
+        )?;
+
+        let file_path = self.data_dir.join("indexed_by_alternate_0.xraystore");
+        Ok(vec![IndexedStore::load(file_path, "by_alternate".to_string(), num_entries)?])
+    }
+}
@@ -0,0 +1,65 @@
+use std::io::{Error, ErrorKind, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use whatlang::Lang;
+
+/// The per-page metadata kept from the WARC headers, stored in the `meta` blob store
+#[derive(Clone, Debug)]
+pub struct PageMeta {
+    pub title: String,
+    pub date: String,
+    pub record_id: String,
+    pub refers_to: String,
+    pub block_digest: String,
+    pub content_length: u64,
+    pub lang: Lang,
+    /// the SimHash of the page's words, 0 for pages imported before SimHashes were kept
+    pub simhash: u64,
+}
+
+fn write_str<W: Write>(writer: &mut W, value: &str) -> Result<(), Error> {
+    // strings are length-prefixed, anything past u16::MAX bytes is dropped
+    let mut len = value.len().min(u16::MAX as usize);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    writer.write_u16::<LittleEndian>(len as u16)?;
+    writer.write_all(&value.as_bytes()[..len])
+}
+
+fn read_str<R: Read>(reader: &mut R) -> Result<String, Error> {
+    let len = reader.read_u16::<LittleEndian>()? as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+}
+
+impl PageMeta {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_str(&mut bytes, &self.title).unwrap();
+        write_str(&mut bytes, &self.date).unwrap();
+        write_str(&mut bytes, &self.record_id).unwrap();
+        write_str(&mut bytes, &self.refers_to).unwrap();
+        write_str(&mut bytes, &self.block_digest).unwrap();
+        bytes.write_u64::<LittleEndian>(self.content_length).unwrap();
+        write_str(&mut bytes, self.lang.code()).unwrap();
+        bytes.write_u64::<LittleEndian>(self.simhash).unwrap();
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<PageMeta, Error> {
+        let reader = &mut bytes;
+        Ok(PageMeta {
+            title: read_str(reader)?,
+            date: read_str(reader)?,
+            record_id: read_str(reader)?,
+            refers_to: read_str(reader)?,
+            block_digest: read_str(reader)?,
+            content_length: reader.read_u64::<LittleEndian>()?,
+            lang: Lang::from_code(read_str(reader)?)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "unknown language code"))?,
+            simhash: reader.read_u64::<LittleEndian>().unwrap_or(0),
+        })
+    }
+}
@@ -1,71 +1,241 @@
+use std::borrow::Cow;
+use std::fs::metadata;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
-use storage::index_storage::append_index;
+use std::time::{Instant, SystemTime};
 
-use rayon::prelude::*;
 use rayon_hash::{HashMap, HashSet};
 use whatlang::Lang;
 
 mod url_storage;
 use storage::url_storage::UrlIndex;
 
+mod blob_storage;
+use storage::blob_storage::BlobIndex;
+
+mod meta_storage;
+pub use storage::meta_storage::PageMeta;
+
 mod index_storage;
-use storage::index_storage::IndexedData;
+use storage::index_storage::{IndexedData, IndexedStore};
 
-use errors::StrError;
-use helpers::ReadableDuration;
+mod store_reader;
+
+mod postings;
+pub use storage::postings::PostingCursor;
+pub use storage::postings::TermCursor;
+
+mod compaction;
+pub use storage::compaction::{Compaction, DEFAULT_BUFFER_SIZE, DEFAULT_FANOUT};
+
+mod merge;
+
+mod snapshot;
+pub use storage::snapshot::{check_urls, snapshot_dir};
 
-const JUMP_STRIDE: u32 = 1000;
+mod dump;
+
+use config::Config;
+use errors::StrError;
+use helpers::{decompress, is_near_duplicate, registered_domain, simhash_bands,
+              term_score, url_host, url_words, ReadableDuration};
+use query::Query;
+
+/// the files that list the stores of a data dir
+const MANIFEST_FILES: [&str; 4] = [
+    "urls.xraystore",
+    "docs.xraystore",
+    "meta.xraystore",
+    "indexed.xraystore",
+];
 
 #[derive(Clone, Debug, Default)]
 struct ImportProcessing {
     by_language: HashMap<Lang, Vec<u64>>,
+    by_date: HashMap<String, Vec<u64>>,
+    by_host: HashMap<String, Vec<u64>>,
+    by_registered_domain: HashMap<String, Vec<u64>>,
     by_word: HashMap<String, Vec<u64>>,
     by_title_word: HashMap<String, Vec<u64>>,
+    by_url_word: HashMap<String, Vec<u64>>,
+    by_digest: HashMap<String, Vec<u64>>,
+    by_alternate: HashMap<String, Vec<u64>>,
+    by_simhash_band: HashMap<String, Vec<u64>>,
     urls: HashMap<u64, String>,
+    docs: HashMap<u64, Vec<u8>>,
+    meta: HashMap<u64, Vec<u8>>,
+}
+
+impl ImportProcessing {
+    /// the sets of a tag keyed by strings, None for the tags that aren't
+    fn tag(&self, tag: &str) -> Option<&HashMap<String, Vec<u64>>> {
+        match tag {
+            "by_date" => Some(&self.by_date),
+            "by_host" => Some(&self.by_host),
+            "by_registered_domain" => Some(&self.by_registered_domain),
+            "by_word" => Some(&self.by_word),
+            "by_title_word" => Some(&self.by_title_word),
+            "by_url_word" => Some(&self.by_url_word),
+            "by_digest" => Some(&self.by_digest),
+            "by_alternate" => Some(&self.by_alternate),
+            "by_simhash_band" => Some(&self.by_simhash_band),
+            _ => None,
+        }
+    }
+}
+
+/// the LSH buckets of every imported page, used to find near duplicates during imports
+#[derive(Clone, Debug, Default)]
+struct SimHashBuckets {
+    bands: HashMap<String, Vec<u64>>,
+    /// the SimHashes of pages imported in this run, which the meta store doesn't have yet
+    recent: HashMap<u64, u64>,
+}
+
+/// the length and modification time of each of the files listing a data dir's stores, which
+/// change whenever pages are imported into it or its stores are compacted or optimized
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Manifest(Vec<Option<(u64, SystemTime)>>);
+
+impl Manifest {
+    fn read(data_dir: &Path) -> Manifest {
+        Manifest(
+            MANIFEST_FILES
+                .iter()
+                .map(|name| {
+                    let metadata = metadata(data_dir.join(name)).ok()?;
+                    Some((metadata.len(), metadata.modified().ok()?))
+                })
+                .collect(),
+        )
+    }
+}
+
+/// the indices of a data dir as they were at one point, loaded by `Storage::load_indices` to be
+/// swapped in by `Storage::swap_indices`
+pub struct LoadedIndices {
+    manifest: Manifest,
+    url_index: UrlIndex,
+    doc_index: BlobIndex,
+    meta_index: BlobIndex,
+    indexed_data: IndexedData,
+}
+
+/// the pages a query's language, date range and site operators allow
+pub struct PageFilter<'a> {
+    /// sets a page must be in all of
+    required: Vec<Cow<'a, HashSet<u64>>>,
+    excluded: HashSet<u64>,
+}
+
+impl<'a> PageFilter<'a> {
+    pub fn allows(&self, id: u64) -> bool {
+        self.required.iter().all(|set| set.contains(&id)) && !self.excluded.contains(&id)
+    }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct Storage {
     data_dir: PathBuf,
+    /// the settings of the data dir, or of the data dir it is a shard of
+    config: Config,
     num_pages: u64,
+    /// block digest to canonical page id, only loaded for imports
+    digests: HashMap<String, u64>,
+    /// only loaded for imports that skip near duplicates
+    simhash_buckets: Option<SimHashBuckets>,
     url_index: UrlIndex,
+    doc_index: BlobIndex,
+    meta_index: BlobIndex,
     indexed_data: IndexedData,
     import_processing: ImportProcessing,
+    /// the state of the data dir's store lists when the indices were loaded
+    manifest: Manifest,
 }
 
 impl Storage {
-    pub fn new<IntoPathBuf: Into<PathBuf>>(data_dir: IntoPathBuf, load_indices: bool) -> Storage {
-        use std::env::set_current_dir;
+    pub fn new<IntoPathBuf: Into<PathBuf>>(
+        data_dir: IntoPathBuf,
+        config: Config,
+        load_indices: bool,
+    ) -> Storage {
         let data_dir = ::std::fs::canonicalize(data_dir.into()).unwrap();
-        set_current_dir(&data_dir).unwrap();
 
         if !load_indices {
             return Storage {
                 data_dir,
+                config,
                 ..Default::default()
             };
         }
 
         let now = Instant::now();
 
-        let url_index = UrlIndex::load().unwrap();
-        let indexed_data = IndexedData::load().unwrap();
+        let mut storage = Storage {
+            data_dir,
+            config,
+            ..Default::default()
+        };
+        storage.reload().unwrap();
 
-        let mut num_pages = 0;
-        for entry in &url_index.0 {
-            num_pages += entry.num_entries;
-        }
+        // on stderr, so that it doesn't end up in the output of `dump`
+        eprintln!("loaded stored indices in {}", now.elapsed().readable());
 
-        println!("loaded stored indices in {}", now.elapsed().readable());
+        storage
+    }
 
-        Storage {
-            data_dir,
-            num_pages,
-            url_index,
-            indexed_data,
-            import_processing: Default::default(),
+    /// the settings pages are analyzed and stores written with
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// loads the indices of the data dir as they are now, which can take a while, without
+    /// changing the ones in use
+    pub fn load_indices(&self) -> Result<LoadedIndices, StrError> {
+        // read first, so that changes made while loading are seen as changes afterwards
+        let manifest = Manifest::read(&self.data_dir);
+
+        Ok(LoadedIndices {
+            manifest,
+            url_index: UrlIndex::load(&self.data_dir)?,
+            doc_index: BlobIndex::load(&self.data_dir, "docs")?,
+            meta_index: BlobIndex::load(&self.data_dir, "meta")?,
+            indexed_data: IndexedData::load(&self.data_dir)?,
+        })
+    }
+
+    /// whether another process has changed the data dir since the indices were loaded
+    pub fn is_stale(&self) -> bool {
+        Manifest::read(&self.data_dir) != self.manifest
+    }
+
+    /// replaces the indices in use with ones loaded by `load_indices`, unless the data dir
+    /// changed again while they were loaded or pages were added that haven't been persisted,
+    /// whose ids follow the pages of the indices in use. returns whether they were replaced
+    pub fn swap_indices(&mut self, indices: LoadedIndices) -> bool {
+        if indices.manifest != Manifest::read(&self.data_dir) || self.num_recent() > 0 {
+            return false;
         }
+
+        self.install_indices(indices);
+        true
+    }
+
+    /// loads the indices of the data dir again, to see the stores written since they were loaded
+    pub fn reload(&mut self) -> Result<(), StrError> {
+        let indices = self.load_indices()?;
+        self.install_indices(indices);
+        Ok(())
+    }
+
+    fn install_indices(&mut self, indices: LoadedIndices) {
+        let num_stored: u64 = indices.url_index.0.iter().map(|store| store.num_entries).sum();
+        self.num_pages = num_stored + self.num_recent() as u64;
+
+        self.manifest = indices.manifest;
+        self.url_index = indices.url_index;
+        self.doc_index = indices.doc_index;
+        self.meta_index = indices.meta_index;
+        self.indexed_data = indices.indexed_data;
     }
 
     pub fn insert_url(&mut self, url: String) -> u64 {
@@ -85,6 +255,180 @@ impl Storage {
             .push(url_id);
     }
 
+    /// indexes a page under its host, registered domain and the words of its URL
+    pub fn index_url(&mut self, url_id: u64, url: &str) {
+        self.insert_host(url_id, url);
+        for word in url_words(url, &self.config.analysis) {
+            self.insert_url_word(url_id, word);
+        }
+    }
+
+    /// records `url_id` as an alternate URL for the identical page `canonical_id`
+    pub fn insert_alternate(&mut self, canonical_id: u64, url_id: u64) {
+        self.import_processing
+            .by_alternate
+            .entry(canonical_id.to_string())
+            .or_default()
+            .push(url_id);
+    }
+
+    /// loads the block digest of every page imported so far, so that identical pages in later
+    /// imports can be recognized
+    pub fn load_digests(&mut self) -> Result<(), StrError> {
+        let mut digests = HashMap::new();
+        for store in self.indexed_data
+            .stores
+            .iter()
+            .filter(|store| store.tag == "by_digest")
+        {
+            for (digest, ids) in store.get_all_words()? {
+                if let Some(&id) = ids.first() {
+                    digests.insert(digest, id);
+                }
+            }
+        }
+
+        self.digests = digests;
+
+        Ok(())
+    }
+
+    /// the id of the canonical page with this block digest, if one has been imported
+    pub fn find_digest(&self, digest: &str) -> Option<u64> {
+        self.digests.get(digest).cloned()
+    }
+
+    /// records `url_id` as the canonical page for its block digest
+    pub fn insert_digest(&mut self, url_id: u64, digest: &str) {
+        self.digests.insert(digest.to_string(), url_id);
+        self.import_processing
+            .by_digest
+            .insert(digest.to_string(), vec![url_id]);
+    }
+
+    /// loads the LSH buckets of every page imported so far, so that near duplicates in later
+    /// imports can be recognized
+    pub fn load_simhash_buckets(&mut self) -> Result<(), StrError> {
+        let mut bands: HashMap<String, Vec<u64>> = HashMap::new();
+        for store in self.indexed_data
+            .stores
+            .iter()
+            .filter(|store| store.tag == "by_simhash_band")
+        {
+            for (band, ids) in store.get_all_words()? {
+                bands.entry(band).or_default().extend(ids);
+            }
+        }
+
+        self.simhash_buckets = Some(SimHashBuckets {
+            bands,
+            recent: HashMap::new(),
+        });
+
+        Ok(())
+    }
+
+    /// the id of an imported page whose SimHash is within `NEAR_DUPLICATE_DISTANCE` bits of this
+    /// one, only pages sharing a bucket with it are compared
+    pub fn find_near_duplicate(&self, hash: u64) -> Option<u64> {
+        let buckets = self.simhash_buckets.as_ref()?;
+        if hash == 0 {
+            return None;
+        }
+
+        let mut candidates = simhash_bands(hash)
+            .iter()
+            .filter_map(|band| buckets.bands.get(band))
+            .flat_map(|ids| ids.iter().cloned())
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let (recent, stored): (Vec<u64>, Vec<u64>) = candidates
+            .into_iter()
+            .partition(|id| buckets.recent.contains_key(id));
+
+        if let Some(id) = recent
+            .into_iter()
+            .find(|id| is_near_duplicate(buckets.recent[id], hash))
+        {
+            return Some(id);
+        }
+
+        let meta = self.get_meta(stored.clone());
+        stored.into_iter().find(|id| {
+            meta.get(id)
+                .is_some_and(|meta| is_near_duplicate(meta.simhash, hash))
+        })
+    }
+
+    /// puts a page into the LSH buckets of its SimHash
+    pub fn insert_simhash(&mut self, url_id: u64, hash: u64) {
+        // a SimHash of 0 means it is unknown
+        if hash == 0 {
+            return;
+        }
+
+        for band in simhash_bands(hash) {
+            if let Some(ref mut buckets) = self.simhash_buckets {
+                buckets.bands.entry(band.clone()).or_default().push(url_id);
+            }
+            self.import_processing
+                .by_simhash_band
+                .entry(band)
+                .or_default()
+                .push(url_id);
+        }
+
+        if let Some(ref mut buckets) = self.simhash_buckets {
+            buckets.recent.insert(url_id, hash);
+        }
+    }
+
+    /// indexes a page under its host and registered domain
+    pub fn insert_host(&mut self, url_id: u64, url: &str) {
+        if let Some(host) = url_host(url) {
+            self.import_processing
+                .by_registered_domain
+                .entry(registered_domain(&host).to_string())
+                .or_default()
+                .push(url_id);
+            self.import_processing
+                .by_host
+                .entry(host)
+                .or_default()
+                .push(url_id);
+        }
+    }
+
+    /// indexes a page under the `YYYY-MM-DD` day of its crawl date
+    pub fn insert_date(&mut self, url_id: u64, date: &str) {
+        if let Some(day) = date.get(..10) {
+            self.import_processing
+                .by_date
+                .entry(day.to_string())
+                .or_default()
+                .push(url_id);
+        }
+    }
+
+    /// stores the compressed text of a page
+    pub fn insert_doc(&mut self, url_id: u64, text: Vec<u8>) {
+        self.import_processing.docs.insert(url_id, text);
+    }
+
+    pub fn insert_meta(&mut self, url_id: u64, meta: &PageMeta) {
+        self.import_processing.meta.insert(url_id, meta.to_bytes());
+    }
+
+    pub fn insert_url_word(&mut self, url_id: u64, word: String) {
+        self.import_processing
+            .by_url_word
+            .entry(word)
+            .or_default()
+            .push(url_id);
+    }
+
     pub fn insert_word(&mut self, url_id: u64, in_title: bool, word: String) {
         let set = if in_title {
             &mut self.import_processing.by_title_word
@@ -95,41 +439,102 @@ impl Storage {
         set.entry(word).or_insert_with(Vec::new).push(url_id);
     }
 
+    /// the number after the highest numbered store for `tag`, which is free to use for a new one
     pub fn next_unique(&self, tag: &str) -> u64 {
+        let prefix = format!("indexed_{}_", tag);
         self.indexed_data
             .stores
             .iter()
             .filter(|store| store.tag == tag)
-            .count() as u64
+            .filter_map(|store| {
+                let file_name = store.file_path.file_name()?.to_str()?;
+                file_name
+                    .strip_prefix(&prefix)?
+                    .strip_suffix(".xraystore")?
+                    .parse::<u64>()
+                    .ok()
+            })
+            .map(|unique| unique + 1)
+            .max()
+            .unwrap_or(0)
     }
 
     pub fn persist(&mut self, unique: Option<u64>) {
-        use std::mem::replace;
-
-        self.persist_urls();
+        let processing = ::std::mem::take(&mut self.import_processing);
+        self.write_stores(processing, unique);
+    }
 
-        let by_language = replace(&mut self.import_processing.by_language, HashMap::new())
+    /// writes pages added in memory out to new stores and adds them to the index files, the
+    /// URLs, docs and meta of the pages first so that the indexed stores never refer to pages
+    /// that aren't stored yet
+    fn write_stores(&self, processing: ImportProcessing, unique: Option<u64>) {
+        let ImportProcessing {
+            by_language,
+            by_date,
+            by_host,
+            by_registered_domain,
+            by_word,
+            by_title_word,
+            by_url_word,
+            by_digest,
+            by_alternate,
+            by_simhash_band,
+            urls,
+            docs,
+            meta,
+        } = processing;
+        let jump_stride = self.config.jump_stride;
+
+        url_storage::store_urls(&self.data_dir, jump_stride, &urls).unwrap();
+        blob_storage::store_blobs(&self.data_dir, "docs", jump_stride, &docs).unwrap();
+        blob_storage::store_blobs(&self.data_dir, "meta", jump_stride, &meta).unwrap();
+
+        let by_language = by_language
             .into_iter()
             .map(|(lang, set)| (lang.code().to_string(), set))
             .collect();
         self.persist_indexed("by_language", unique, by_language);
-
-        let by_title_word = replace(&mut self.import_processing.by_title_word, HashMap::new())
-            .into_iter()
-            .collect();
-        self.persist_indexed("by_title_word", unique, by_title_word);
-
-        let by_word = replace(&mut self.import_processing.by_word, HashMap::new())
-            .into_iter()
-            .collect();
-        self.persist_indexed("by_word", unique, by_word);
+        self.persist_indexed("by_date", unique, by_date.into_iter().collect());
+        self.persist_indexed("by_host", unique, by_host.into_iter().collect());
+        let by_registered_domain = by_registered_domain.into_iter().collect();
+        self.persist_indexed("by_registered_domain", unique, by_registered_domain);
+        self.persist_indexed("by_title_word", unique, by_title_word.into_iter().collect());
+        self.persist_indexed("by_url_word", unique, by_url_word.into_iter().collect());
+        self.persist_indexed("by_digest", unique, by_digest.into_iter().collect());
+        self.persist_indexed("by_alternate", unique, by_alternate.into_iter().collect());
+        self.persist_indexed("by_simhash_band", unique, by_simhash_band.into_iter().collect());
+        self.persist_indexed("by_word", unique, by_word.into_iter().collect());
     }
 
     pub fn persist_urls(&mut self) {
-        url_storage::store_urls(&self.import_processing.urls).unwrap();
+        let urls = &self.import_processing.urls;
+        url_storage::store_urls(&self.data_dir, self.config.jump_stride, urls).unwrap();
         self.import_processing.urls = HashMap::new();
     }
 
+    /// the number of pages added since they were last persisted
+    pub fn num_recent(&self) -> usize {
+        self.import_processing.urls.len()
+    }
+
+    /// writes the pages added since the last flush out to new stores and loads the indices with
+    /// them, without changing the ones in use. the pages stay searchable in memory until
+    /// `finish_flush` swaps the indices in, None if there are no pages to write
+    pub fn write_recent(&self) -> Result<Option<LoadedIndices>, StrError> {
+        if self.num_recent() == 0 {
+            return Ok(None);
+        }
+
+        self.write_stores(self.import_processing.clone(), None);
+        Ok(Some(self.load_indices()?))
+    }
+
+    /// swaps in the indices `write_recent` loaded and drops the pages they have from memory
+    pub fn finish_flush(&mut self, indices: LoadedIndices) {
+        self.import_processing = ImportProcessing::default();
+        self.install_indices(indices);
+    }
+
     pub fn persist_indexed(
         &self,
         tag: &str,
@@ -138,8 +543,10 @@ impl Storage {
     ) {
         let unique = unique.unwrap_or_else(|| self.next_unique(tag));
         index_storage::store_indexed(
+            &self.data_dir,
             tag,
             unique,
+            self.config.jump_stride,
             indexed_data
                 .into_iter()
                 .map(|(word, data)| (word, data.into_iter().collect()))
@@ -147,16 +554,37 @@ impl Storage {
         ).unwrap();
     }
 
-    pub fn num_indexed_stores(&mut self) -> usize {
-        self.indexed_data.stores.len()
+    /// the number after the highest numbered store of any tag, import chunks numbered from it
+    /// don't collide with existing stores
+    pub fn num_indexed_chunks(&self) -> usize {
+        self.tags()
+            .iter()
+            .map(|tag| self.next_unique(tag) as usize)
+            .max()
+            .unwrap_or(0)
     }
 
-    #[allow(unused)]
-    pub fn reload(&mut self) {
-        *self = Storage::new(self.data_dir.clone(), true);
+    /// every tag that has at least one indexed store
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags = self.indexed_data
+            .stores
+            .iter()
+            .map(|store| store.tag.clone())
+            .collect::<Vec<_>>();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
     }
 
-    pub fn optimize_tag(&mut self, tag: &str, chunk_size: usize) -> Result<(), StrError> {
+    /// merges every store for `tag` into new stores of at most `chunk_size` words each, numbered
+    /// after the existing ones, which stay in the index until `optimize` swaps them out.
+    /// returns the path and number of words of each store written
+    pub fn optimize_tag(
+        &self,
+        tag: &str,
+        chunk_size: usize,
+        buffer_size: usize,
+    ) -> Result<Vec<(PathBuf, u64)>, StrError> {
         println!("optimizing {}", tag);
         let stores = self.indexed_data
             .stores
@@ -164,59 +592,18 @@ impl Storage {
             .filter(|store| store.tag == tag)
             .collect::<Vec<_>>();
 
-        let words = stores.iter().map(|store| store.num_entries).max().unwrap();
-
-        (0..words)
-            .collect::<Vec<_>>()
-            .par_chunks(chunk_size)
-            .enumerate()
-            .for_each(|(chunk_num, chunk)| {
-                println!("stores.get_words - {}", chunk_num);
-                let store_data = stores
-                    .par_iter()
-                    .map(|store| {
-                        let map = match store.get_subset_of_words(chunk[0], chunk.len()) {
-                            Ok(map) => map,
-                            Err(err) => {
-                                panic!("store: {}, err: {:?}", store.file_path.display(), err);
-                            }
-                        };
-                        map
-                    })
-                    .collect::<Vec<_>>();
-                println!("aggregating into new_data - {}", chunk_num);
-
-                let mut all_words = store_data
-                    .iter()
-                    .flat_map(|data| data.iter().map(|&(ref word, _)| word.clone()))
-                    .collect::<Vec<_>>();
-
-                all_words.par_sort_unstable();
-                all_words.dedup();
-
-                let mut new_data: Vec<(String, Vec<u64>)> = all_words
-                    .into_iter()
-                    .map(|word| (word, Vec::new()))
-                    .collect();
-
-                for data in store_data {
-                    for (word, mut set) in data {
-                        match new_data.binary_search_by(|&(ref probe, _)| probe.cmp(&word)) {
-                            Ok(idx) => new_data[idx].1.append(&mut set),
-                            Err(_) => panic!("invalid word!"),
-                        };
-                    }
-                }
-
-                println!("persisting data to disk - {}", chunk_num);
-                index_storage::store_indexed(
-                    &(tag.to_string() + "_tmp"),
-                    chunk_num as u64,
-                    new_data,
-                ).unwrap();
-            });
-
-        Ok(())
+        let written = index_storage::merge_stores(
+            &self.data_dir,
+            &stores,
+            tag,
+            self.next_unique(tag),
+            chunk_size as u64,
+            self.config.jump_stride,
+            buffer_size,
+        )?;
+        println!("merged {} stores into {}", stores.len(), written.len());
+
+        Ok(written)
     }
 
     pub fn rebuild_index(&mut self) -> Result<(), StrError> {
@@ -228,9 +615,8 @@ impl Storage {
             for entry in read_dir(path)? {
                 let entry = entry?;
                 let entry_path = entry.path();
-                if entry_path.is_dir() {
-                    results.extend(traverse(&entry_path)?);
-                } else if entry_path.is_file() {
+                // sub-directories are shards or snapshots, whose stores aren't this index's
+                if entry_path.is_file() {
                     let file_name = entry_path
                         .file_name()
                         .unwrap()
@@ -259,64 +645,289 @@ impl Storage {
             Ok(results)
         }
 
-        let index = traverse(&self.data_dir)?;
-
-        File::create("indexed.xraystore")?;
-        for (index_path, num_entries, tag) in index {
-            append_index(
-                &index_path.into_os_string().into_string().unwrap(),
-                &tag,
-                num_entries,
-            )?;
+        let mut stores = Vec::new();
+        for (index_path, num_entries, tag) in traverse(&self.data_dir)? {
+            stores.push(IndexedStore::load(index_path, tag, num_entries)?);
         }
 
+        // the new index replaces the old one in one step, so that snapshots see either of them
+        index_storage::write_index(&self.data_dir, &stores)?;
+
         Ok(())
     }
 
-    pub fn optimize(&mut self, chunk_size: usize) -> Result<(), StrError> {
+    pub fn optimize(&mut self, chunk_size: usize, buffer_size: usize) -> Result<(), StrError> {
         use std::fs::remove_file;
+        use std::mem::replace;
 
-        for tag in &["by_word", "by_title_word", "by_language"] {
-            self.optimize_tag(tag, chunk_size)?;
+        let mut optimized = Vec::new();
+        for tag in self.tags() {
+            for (file_path, num_entries) in self.optimize_tag(&tag, chunk_size, buffer_size)? {
+                optimized.push(IndexedStore::load(file_path, tag.clone(), num_entries)?);
+            }
         }
 
-        for store in self.indexed_data.stores.drain(..) {
+        // the new stores replace the old ones in the index before the old ones are removed, so
+        // that the index never lists a store that is gone, like `compact` does
+        let old = replace(&mut self.indexed_data.stores, optimized);
+        index_storage::write_index(&self.data_dir, &self.indexed_data.stores)?;
+
+        for store in old {
             remove_file(store.file_path)?;
         }
-        remove_file(self.data_dir.join("indexed.xraystore"))?;
-
-        self.rebuild_index()?;
 
         Ok(())
     }
 
-    /// gets the associated HashSets for each word, filtered by language
-    /// returns (title_words, content_words)
-    pub fn get_word_sets(
+    /// builds the filter for the query's language, date range and site operators, or None if
+    /// no page can pass it
+    pub fn get_filter(&self, query: &Query) -> Option<PageFilter<'_>> {
+        let mut required = Vec::new();
+
+        if let Some(lang) = query.lang {
+            let stored = self.indexed_data.langs.get(lang.code());
+            match (stored, self.import_processing.by_language.get(&lang)) {
+                (Some(set), None) => required.push(Cow::Borrowed(set)),
+                // no pages in this language exist, so nothing can match
+                (None, None) => return None,
+                (stored, recent) => required.push(Cow::Owned(
+                    stored
+                        .into_iter()
+                        .flatten()
+                        .chain(recent.into_iter().flatten())
+                        .cloned()
+                        .collect(),
+                )),
+            }
+        }
+
+        if query.has_date_range() {
+            required.push(Cow::Owned(self.get_date_set(query)));
+        }
+
+        if !query.sites.is_empty() {
+            required.push(Cow::Owned(self.get_site_set(&query.sites)));
+        }
+
+        if !query.hosts.is_empty() {
+            let mut pages = HashSet::new();
+            for host in &query.hosts {
+                pages.extend(self.get_host_set(host));
+            }
+            required.push(Cow::Owned(pages));
+        }
+
+        for fragment in &query.inurl {
+            required.push(Cow::Owned(self.get_inurl_set(fragment)));
+        }
+
+        Some(PageFilter {
+            required,
+            excluded: self.get_site_set(&query.excluded_sites),
+        })
+    }
+
+    /// opens a cursor for each word in the store for `tag`, with the word's score multiplied by
+    /// `weight` as what a page scores by matching it
+    pub fn get_terms(
         &self,
-        lang: Lang,
-        words: Vec<String>,
-    ) -> (HashMap<String, HashSet<u64>>, HashMap<String, HashSet<u64>>) {
-        let lang = self.indexed_data.langs.get(lang.code()).unwrap();
+        tag: &str,
+        mut words: Vec<String>,
+        weight: usize,
+    ) -> Result<Vec<TermCursor>, StrError> {
+        words.sort_unstable();
+        words.dedup();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
 
-        // get the sets and then filter by the current language
+        let mut cursors = self.indexed_data.get_cursors(tag, &words)?;
+        if let Some(recent) = self.import_processing.tag(tag) {
+            for word in &words {
+                if let Some(set) = recent.get(word) {
+                    cursors
+                        .entry(word.clone())
+                        .or_default()
+                        .push(PostingCursor::in_memory(set.clone()));
+                }
+            }
+        }
 
-        let content_words = self.indexed_data
-            .get_words("by_word", words.clone())
+        Ok(words
             .into_iter()
-            .map(|(word, set)| (word, &set & lang))
-            .collect();
+            .filter_map(|word| {
+                let segments = cursors.remove(&word)?;
+                let score = term_score(&word) * weight;
+                Some(TermCursor::new(word, score, segments))
+            })
+            .collect())
+    }
+
+    /// like `IndexedData::get_words`, but also with the pages that haven't been persisted yet
+    pub fn get_words(&self, tag: &str, words: Vec<String>) -> HashMap<String, HashSet<u64>> {
+        let mut word_map = self.indexed_data.get_words(tag, words.clone());
+        if let Some(recent) = self.import_processing.tag(tag) {
+            for word in words {
+                if let Some(set) = recent.get(&word) {
+                    word_map.entry(word).or_default().extend(set.iter().cloned());
+                }
+            }
+        }
+
+        word_map
+    }
+
+    /// gets every page crawled within the query's date range
+    pub fn get_date_set(&self, query: &Query) -> HashSet<u64> {
+        let mut days = self.indexed_data
+            .dates
+            .iter()
+            .chain(self.import_processing.by_date.keys())
+            .filter(|day| query.matches_date(day))
+            .cloned()
+            .collect::<Vec<_>>();
+        days.sort_unstable();
+        days.dedup();
+
+        let mut pages = HashSet::new();
+        for (_, set) in self.get_words("by_date", days) {
+            pages.extend(set);
+        }
 
-        let title_words = self.indexed_data
-            .get_words("by_title_word", words)
+        pages
+    }
+
+    /// gets every page on any of the sites, where a site that is a registered domain also
+    /// covers all of its subdomains
+    pub fn get_site_set(&self, sites: &[String]) -> HashSet<u64> {
+        let (domains, hosts): (Vec<String>, Vec<String>) = sites
+            .iter()
+            .cloned()
+            .partition(|site| registered_domain(site) == site);
+
+        let mut pages = HashSet::new();
+        let domain_sets = self.get_words("by_registered_domain", domains);
+        let host_sets = self.get_words("by_host", hosts);
+        for (_, set) in domain_sets.into_iter().chain(host_sets) {
+            pages.extend(set);
+        }
+
+        pages
+    }
+
+    /// gets every page on exactly this host, not counting its subdomains
+    pub fn get_host_set(&self, host: &str) -> HashSet<u64> {
+        self.get_words("by_host", vec![host.to_string()])
             .into_iter()
-            .map(|(word, set)| (word, &set & lang))
-            .collect();
+            .flat_map(|(_, set)| set)
+            .collect()
+    }
+
+    /// gets every page whose host contains the fragment, or whose URL contains it as a word
+    pub fn get_inurl_set(&self, fragment: &str) -> HashSet<u64> {
+        let mut hosts = self.indexed_data
+            .hosts
+            .iter()
+            .chain(self.import_processing.by_host.keys())
+            .filter(|host| host.contains(fragment))
+            .cloned()
+            .collect::<Vec<_>>();
+        hosts.sort_unstable();
+        hosts.dedup();
+
+        let mut pages = HashSet::new();
+        for (_, set) in self.get_words("by_host", hosts) {
+            pages.extend(set);
+        }
+
+        let url_words = self.config.analysis.canonicalize(fragment).into_iter().collect();
+        for (_, set) in self.get_words("by_url_word", url_words) {
+            pages.extend(set);
+        }
 
-        (title_words, content_words)
+        pages
+    }
+
+    /// the languages that have at least one page in the index
+    pub fn langs(&self) -> Vec<Lang> {
+        let mut langs = self.indexed_data
+            .langs
+            .keys()
+            .filter_map(|code| Lang::from_code(code.as_str()))
+            .collect::<Vec<_>>();
+        for &lang in self.import_processing.by_language.keys() {
+            if !langs.contains(&lang) {
+                langs.push(lang);
+            }
+        }
+        langs
     }
 
     pub fn get_urls(&self, urls: Vec<u64>) -> HashMap<u64, String> {
-        self.url_index.get_urls(urls)
+        let (recent, stored): (Vec<u64>, Vec<u64>) = urls
+            .into_iter()
+            .partition(|id| self.import_processing.urls.contains_key(id));
+
+        let mut found = self.url_index.get_urls(stored);
+        for id in recent {
+            found.insert(id, self.import_processing.urls[&id].clone());
+        }
+
+        found
+    }
+
+    /// gets the blobs of the pages from `index`, or from `recent` for the pages that haven't been
+    /// persisted yet
+    fn get_blobs(
+        index: &BlobIndex,
+        recent: &HashMap<u64, Vec<u8>>,
+        urls: Vec<u64>,
+    ) -> HashMap<u64, Vec<u8>> {
+        let (recent_ids, stored): (Vec<u64>, Vec<u64>) =
+            urls.into_iter().partition(|id| recent.contains_key(id));
+
+        let mut blobs = index.get_blobs(stored);
+        for id in recent_ids {
+            blobs.insert(id, recent[&id].clone());
+        }
+
+        blobs
+    }
+
+    /// gets the stored text of each page that has any
+    pub fn get_docs(&self, urls: Vec<u64>) -> HashMap<u64, String> {
+        Storage::get_blobs(&self.doc_index, &self.import_processing.docs, urls)
+            .into_iter()
+            .filter_map(|(id, data)| Some((id, decompress(&data)?)))
+            .collect()
+    }
+
+    /// gets the alternate URLs recorded for each page that has any, leaving out the ones whose
+    /// URL isn't stored
+    pub fn get_alternates(&self, urls: Vec<u64>) -> HashMap<u64, Vec<String>> {
+        let alternates =
+            self.get_words("by_alternate", urls.iter().map(|id| id.to_string()).collect());
+
+        let alternate_ids = alternates.values().flat_map(|set| set.iter().cloned()).collect();
+        let alternate_urls = self.get_urls(alternate_ids);
+
+        alternates
+            .into_iter()
+            .map(|(id, set)| {
+                let mut ids = set.into_iter().collect::<Vec<_>>();
+                ids.sort_unstable();
+                // alternates without a URL, like ones of an import that didn't finish, are left out
+                let urls = ids.iter().filter_map(|id| alternate_urls.get(id).cloned()).collect();
+                (id.parse().unwrap(), urls)
+            })
+            .collect()
+    }
+
+    /// gets the stored metadata of each page that has any
+    pub fn get_meta(&self, urls: Vec<u64>) -> HashMap<u64, PageMeta> {
+        Storage::get_blobs(&self.meta_index, &self.import_processing.meta, urls)
+            .into_iter()
+            .filter_map(|(id, data)| Some((id, PageMeta::from_bytes(&data).ok()?)))
+            .collect()
     }
 }
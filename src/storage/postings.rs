@@ -0,0 +1,281 @@
+use std::io::{Error, Read, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use storage::store_reader::StoreReader;
+
+/// the number of postings read from disk at a time
+const BLOCK_LEN: u64 = 128;
+
+/// A cursor over the sorted postings of one word in one indexed store, reading them from disk a
+/// block at a time so that skipping ahead doesn't read the postings in between. postings of
+/// pages that haven't been persisted yet are held in memory as a single block instead
+#[derive(Debug)]
+pub struct PostingCursor {
+    /// None for postings held in memory
+    file: Option<StoreReader>,
+    /// where the postings start in the file
+    offset: u64,
+    len: u64,
+    /// the loaded block, and the index of its first posting
+    block: Vec<u64>,
+    block_start: u64,
+    /// the index of the current posting
+    pos: u64,
+    current: Option<u64>,
+}
+
+impl PostingCursor {
+    pub fn open(file: StoreReader, offset: u64, len: u64) -> Result<PostingCursor, Error> {
+        let mut cursor = PostingCursor {
+            file: Some(file),
+            offset,
+            len,
+            block: Vec::new(),
+            block_start: 0,
+            pos: 0,
+            current: None,
+        };
+        if len > 0 {
+            cursor.load_block(0)?;
+            cursor.current = Some(cursor.block[0]);
+        }
+
+        Ok(cursor)
+    }
+
+    /// a cursor over sorted postings that are already in memory
+    pub fn in_memory(postings: Vec<u64>) -> PostingCursor {
+        PostingCursor {
+            file: None,
+            offset: 0,
+            len: postings.len() as u64,
+            current: postings.first().cloned(),
+            block: postings,
+            block_start: 0,
+            pos: 0,
+        }
+    }
+
+    /// the number of postings, wherever the cursor is
+    pub fn num_postings(&self) -> u64 {
+        self.len
+    }
+
+    fn file(&mut self) -> &mut StoreReader {
+        // in-memory postings are one block, which is loaded from the start
+        self.file.as_mut().expect("in-memory postings have no blocks to load")
+    }
+
+    /// the page id the cursor is at, None once it is exhausted
+    pub fn current(&self) -> Option<u64> {
+        self.current
+    }
+
+    fn load_block(&mut self, block: u64) -> Result<(), Error> {
+        let start = block * BLOCK_LEN;
+        let len = BLOCK_LEN.min(self.len - start);
+
+        let mut bytes = vec![0; len as usize * 8];
+        let offset = self.offset + start * 8;
+        let file = self.file();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+
+        let mut reader = &bytes[..];
+        self.block.clear();
+        for _ in 0..len {
+            self.block.push(reader.read_u64::<LittleEndian>()?);
+        }
+        self.block_start = start;
+
+        Ok(())
+    }
+
+    /// reads the last posting of a block without loading it
+    fn block_last(&mut self, block: u64) -> Result<u64, Error> {
+        let last = ((block + 1) * BLOCK_LEN).min(self.len) - 1;
+        let offset = self.offset + last * 8;
+        let file = self.file();
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_u64::<LittleEndian>()
+    }
+
+    /// moves the cursor to the first posting that is at least `target`
+    pub fn seek(&mut self, target: u64) -> Result<Option<u64>, Error> {
+        match self.current {
+            Some(current) if current < target => {}
+            current => return Ok(current),
+        }
+
+        let block_end = self.block_start + self.block.len() as u64;
+        if self.block.last().is_none_or(|&last| last < target) {
+            if block_end >= self.len {
+                // the last block is loaded, so there is nothing left to search
+                self.pos = self.len;
+                self.current = None;
+                return Ok(None);
+            }
+
+            // binary search the remaining blocks for the first one that can contain the target
+            let mut low = block_end / BLOCK_LEN;
+            let mut high = self.len.div_ceil(BLOCK_LEN);
+            while low < high {
+                let mid = low + (high - low) / 2;
+                if self.block_last(mid)? < target {
+                    low = mid + 1;
+                } else {
+                    high = mid;
+                }
+            }
+
+            if low * BLOCK_LEN >= self.len {
+                self.pos = self.len;
+                self.current = None;
+                return Ok(None);
+            }
+            self.load_block(low)?;
+        }
+
+        let from = self.pos.saturating_sub(self.block_start) as usize;
+        let idx = from + match self.block[from..].binary_search(&target) {
+            Ok(idx) | Err(idx) => idx,
+        };
+        self.pos = self.block_start + idx as u64;
+        self.current = Some(self.block[idx]);
+
+        Ok(self.current)
+    }
+}
+
+/// A query term: the cursors over a word's postings in every store of a tag, and what a page
+/// scores by matching it, which is the same for every page that has the word. that makes the
+/// score its own max score bound, so no bound is stored in the index for it. a score that depends
+/// on the page, or on statistics like the length of the postings, would need the max score of
+/// each word to be stored with its postings again
+#[derive(Debug)]
+pub struct TermCursor {
+    pub word: String,
+    pub score: usize,
+    segments: Vec<PostingCursor>,
+}
+
+impl TermCursor {
+    pub fn new(word: String, score: usize, segments: Vec<PostingCursor>) -> TermCursor {
+        TermCursor {
+            word,
+            score,
+            segments,
+        }
+    }
+
+    /// the number of pages that have the word, across the segments
+    pub fn num_postings(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.num_postings()).sum()
+    }
+
+    /// the lowest page id any of the segments is at
+    pub fn current(&self) -> Option<u64> {
+        self.segments
+            .iter()
+            .filter_map(|segment| segment.current())
+            .min()
+    }
+
+    /// moves every segment to its first posting that is at least `target`
+    pub fn seek(&mut self, target: u64) -> Result<Option<u64>, Error> {
+        for segment in &mut self.segments {
+            segment.seek(target)?;
+        }
+
+        Ok(self.current())
+    }
+
+    pub fn advance(&mut self) -> Result<Option<u64>, Error> {
+        match self.current() {
+            Some(current) => self.seek(current + 1),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{remove_file, File};
+    use std::io::Write;
+    use std::process;
+    use std::sync::Arc;
+
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    use super::*;
+
+    /// a cursor over postings written to a file after some other bytes, like a store's header
+    fn on_disk(name: &str, postings: &[u64]) -> PostingCursor {
+        let path = ::std::env::temp_dir().join(format!("xray_{}_{}", name, process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(&[0xff; 13]).unwrap();
+        for &id in postings {
+            file.write_u64::<LittleEndian>(id).unwrap();
+        }
+        drop(file);
+
+        let file = Arc::new(File::open(&path).unwrap());
+        // the open file stays readable once it is removed
+        remove_file(&path).unwrap();
+        PostingCursor::open(StoreReader::new(file), 13, postings.len() as u64).unwrap()
+    }
+
+    fn first_at_least(postings: &[u64], target: u64) -> Option<u64> {
+        postings.iter().cloned().find(|&id| id >= target)
+    }
+
+    #[test]
+    fn seek_across_block_boundaries() {
+        let postings = (0..1000).map(|i| i * 3 + 1).collect::<Vec<_>>();
+        let block = BLOCK_LEN as usize;
+
+        // exact postings and the gaps around the ends of blocks, then jumps over several blocks
+        let mut targets = vec![0, 1, 2];
+        for &idx in &[block - 1, block, block + 1, 2 * block - 1, 2 * block] {
+            targets.extend(&[postings[idx] - 1, postings[idx], postings[idx] + 1]);
+        }
+        targets.extend(&[postings[5 * block + 3], postings[999] - 2, postings[999]]);
+
+        let cursors = &mut [on_disk("seek", &postings), PostingCursor::in_memory(postings.clone())];
+        for cursor in cursors {
+            for &target in &targets {
+                assert_eq!(cursor.seek(target).unwrap(), first_at_least(&postings, target));
+            }
+            // seeking back doesn't move the cursor
+            assert_eq!(cursor.seek(0).unwrap(), Some(postings[999]));
+            assert_eq!(cursor.seek(postings[999] + 1).unwrap(), None);
+            assert_eq!(cursor.seek(postings[999] + 1000).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn seek_to_every_posting() {
+        let postings = (0..BLOCK_LEN * 3 + 5).map(|i| i * 2).collect::<Vec<_>>();
+        let mut cursor = on_disk("walk", &postings);
+        assert_eq!(cursor.num_postings(), postings.len() as u64);
+
+        let mut walked = Vec::new();
+        while let Some(id) = cursor.current() {
+            walked.push(id);
+            cursor.seek(id + 1).unwrap();
+        }
+        assert_eq!(walked, postings);
+    }
+
+    #[test]
+    fn seek_past_a_whole_last_block() {
+        // the last block is full, so the end of the postings is also the end of a block
+        let postings = (0..BLOCK_LEN * 2).collect::<Vec<_>>();
+        let mut cursor = on_disk("full", &postings);
+        assert_eq!(cursor.seek(BLOCK_LEN * 2 - 1).unwrap(), Some(BLOCK_LEN * 2 - 1));
+        assert_eq!(cursor.seek(BLOCK_LEN * 2).unwrap(), None);
+
+        assert_eq!(on_disk("empty", &[]).current(), None);
+    }
+}
@@ -0,0 +1,189 @@
+use std::fs::{copy, create_dir, hard_link, read, remove_dir_all, File};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::Duration;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use errors::StrError;
+use storage::index_storage::{IndexedStore, WordStream};
+
+/// the index files of a data dir, and whether their entries start with a tag like the overall
+/// index does, rather than with a pair of ids. imports add the URLs, docs and meta of their pages
+/// before the indexed stores that refer to them, so reading the overall index first means that
+/// every page the copy indexes is in the stores read after it
+const INDEX_FILES: [(&str, bool); 4] = [
+    ("indexed.xraystore", true),
+    ("urls.xraystore", false),
+    ("docs.xraystore", false),
+    ("meta.xraystore", false),
+];
+
+/// One entry of an index file, kept as the bytes in front of the store path so that it can be
+/// written out again pointing to a different path
+struct IndexEntry {
+    header: Vec<u8>,
+    /// the number of words the entry says an indexed store has
+    num_entries: Option<u64>,
+    path: String,
+}
+
+fn read_entry(reader: &mut &[u8], tagged: bool) -> Result<IndexEntry, Error> {
+    let mut header = Vec::new();
+    let num_entries = if tagged {
+        let tag_len = reader.read_u8()?;
+        let mut tag = vec![0; tag_len as usize];
+        reader.read_exact(&mut tag)?;
+        let num_entries = reader.read_u64::<LittleEndian>()?;
+
+        header.write_u8(tag_len)?;
+        header.write_all(&tag)?;
+        header.write_u64::<LittleEndian>(num_entries)?;
+        Some(num_entries)
+    } else {
+        let mut ids = [0; 16];
+        reader.read_exact(&mut ids)?;
+        header.write_all(&ids)?;
+        None
+    };
+
+    let path_len = reader.read_u16::<LittleEndian>()? as usize;
+    let mut path = vec![0; path_len];
+    reader.read_exact(&mut path)?;
+    let path = String::from_utf8(path).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+
+    Ok(IndexEntry {
+        header,
+        num_entries,
+        path,
+    })
+}
+
+/// parses the complete entries of an index file, leaving out one that is still being appended
+fn read_index(mut bytes: &[u8], tagged: bool) -> Result<Vec<IndexEntry>, Error> {
+    let mut entries = Vec::new();
+    loop {
+        match read_entry(&mut bytes, tagged) {
+            Ok(entry) => entries.push(entry),
+            Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(entries),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// hardlinks a file, or copies it when that isn't possible, like across file systems
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), Error> {
+    if hard_link(from, to).is_err() {
+        copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// copies the store files the index files of `from` refer to at this moment into the new dir
+/// `to`, followed by those index files, checking that every store is the one the index refers to
+fn copy_stores(from: &Path, to: &Path) -> Result<(), StrError> {
+    create_dir(to)?;
+
+    for &(index_file, tagged) in &INDEX_FILES {
+        // the index files are read first, the stores they refer to don't change after that
+        // other than being removed or replaced by compactions and optimizations
+        let mut index = Vec::new();
+        for entry in read_index_file(from, index_file, tagged)? {
+            // stores are kept next to the index in the copy, wherever they were before
+            let file_name = Path::new(&entry.path)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| format!("{} has an invalid store path", index_file))?
+                .to_string();
+            link_or_copy(&from.join(&entry.path), &to.join(&file_name))
+                .map_err(|err| format!("couldn't copy {}: {}", entry.path, err))?;
+
+            if let Some(num_entries) = entry.num_entries {
+                let stored = File::open(to.join(&file_name))?.read_u64::<LittleEndian>()?;
+                if stored != num_entries {
+                    Err(format!("{} was replaced while it was copied", entry.path))?;
+                }
+            }
+
+            index.write_all(&entry.header)?;
+            index.write_u16::<LittleEndian>(file_name.len() as u16)?;
+            index.write_all(file_name.as_bytes())?;
+        }
+
+        File::create(to.join(index_file))?.write_all(&index)?;
+    }
+
+    Ok(())
+}
+
+/// reads the index file `index_file` of `dir`, which is empty when there is none
+fn read_index_file(dir: &Path, index_file: &str, tagged: bool) -> Result<Vec<IndexEntry>, Error> {
+    match read(dir.join(index_file)) {
+        Ok(bytes) => read_index(&bytes, tagged),
+        Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// checks that every page the indexed stores of the data dir `dir` refer to has a URL, which a
+/// copy the index files were read out of order for doesn't have
+pub fn check_urls(dir: &Path) -> Result<(), StrError> {
+    // the ids each URL store holds, looked up the way searches find the store of an id
+    let mut url_ranges = read_index_file(dir, "urls.xraystore", false)?
+        .into_iter()
+        .map(|entry| {
+            let mut ids = &entry.header[..];
+            let first_index = ids.read_u64::<LittleEndian>()?;
+            let num_entries = ids.read_u64::<LittleEndian>()?;
+            Ok((first_index, first_index + num_entries))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    url_ranges.sort_unstable();
+    let has_url = |id: u64| match url_ranges.binary_search(&(id, u64::MAX)) {
+        Ok(_) => true,
+        Err(0) => false,
+        Err(pos) => id < url_ranges[pos - 1].1,
+    };
+
+    for entry in read_index_file(dir, "indexed.xraystore", true)? {
+        let num_entries = entry.num_entries.unwrap_or(0);
+        let store = IndexedStore::load(dir.join(&entry.path), String::new(), num_entries)?;
+        let mut words = WordStream::open(&store, 0, 1 << 16)?;
+        while let Some(word) = words.current().map(|(word, _)| word.to_string()) {
+            while let Some(id) = words.next_id()? {
+                if !has_url(id) {
+                    let path = &entry.path;
+                    Err(format!("{} has page {} under {}, which has no URL", path, id, word))?;
+                }
+            }
+            words.next_word()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// makes a consistent copy of the data dir `from` in the new dir `to`, which shares the store
+/// files with it where possible. a copy that a concurrent import, compaction or optimization
+/// got in the way of is thrown away and made again, up to `attempts` times
+pub fn snapshot_dir(from: &Path, to: &Path, attempts: usize) -> Result<(), StrError> {
+    let mut attempt = 1;
+    loop {
+        match copy_stores(from, to) {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                if to.exists() {
+                    remove_dir_all(to)?;
+                }
+                if attempt >= attempts {
+                    return Err(err);
+                }
+                println!("{} changed while copying it ({}), retrying", from.display(), err.0);
+            }
+        }
+
+        attempt += 1;
+        sleep(Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// A reader over a store file that was opened when the store was loaded. the open file stays
+/// readable after a compaction or optimization removes or replaces it, so queries that are
+/// running while the store is swapped out of the index still finish. each reader keeps its own
+/// position, so any number of them can read the same file at once
+#[derive(Clone, Debug)]
+pub struct StoreReader {
+    file: Arc<File>,
+    pos: u64,
+}
+
+impl StoreReader {
+    pub fn new(file: Arc<File>) -> StoreReader {
+        StoreReader { file, pos: 0 }
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, offset)
+}
+
+impl Read for StoreReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = read_at(&self.file, buf, self.pos)?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for StoreReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.file.metadata()?.len(), offset),
+        };
+
+        self.pos = base
+            .checked_add_signed(offset)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
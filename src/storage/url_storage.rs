@@ -1,14 +1,13 @@
 use errors::StrError;
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::u64;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use rayon::prelude::*;
 use rayon_hash::HashMap;
 
-use super::JUMP_STRIDE;
 
 #[derive(Clone, Debug)]
 pub struct UrlStore {
@@ -20,8 +19,7 @@ pub struct UrlStore {
 }
 
 impl UrlStore {
-    fn load(file_path: String, first_index: u64, num_entries: u64) -> Result<UrlStore, Error> {
-        let file_path: PathBuf = file_path.into();
+    fn load(file_path: PathBuf, first_index: u64, num_entries: u64) -> Result<UrlStore, Error> {
         let mut file = BufReader::new(File::open(&file_path)?);
 
         let jump_table_len = file.read_u64::<LittleEndian>()?;
@@ -106,24 +104,65 @@ impl UrlStore {
     }
 }
 
+impl UrlStore {
+    /// reads the URLs in id order starting at `from`, passing each to `visit` until it returns
+    /// false or the store runs out
+    pub fn scan<F>(&self, from: u64, mut visit: F) -> Result<(), Error>
+    where
+        F: FnMut(u64, String) -> Result<bool, Error>,
+    {
+        let mut file = BufReader::new(File::open(&self.file_path)?);
+
+        // length of the jump table + len(jump_stride) + len(num_entries)
+        let start_offset = self.jump_table.len() as u64 * 8 + 12;
+
+        // the jump table has the location of every `jump_stride`th URL after the first
+        let jump = (from.saturating_sub(self.first_index) / self.jump_stride) as usize;
+        let offset = match jump.min(self.jump_table.len()) {
+            0 => 0,
+            jump => self.jump_table[jump - 1],
+        };
+        file.seek(SeekFrom::Start(start_offset + offset))?;
+
+        loop {
+            let url_len = match file.read_u16::<LittleEndian>() {
+                Ok(url_len) => url_len as usize,
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            let id = file.read_u64::<LittleEndian>()?;
+            if id < from {
+                file.seek(SeekFrom::Current(url_len as i64))?;
+                continue;
+            }
+
+            let mut url_bytes = vec![0; url_len];
+            file.read_exact(&mut url_bytes)?;
+            if !visit(id, String::from_utf8(url_bytes).unwrap())? {
+                return Ok(());
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct UrlIndex(pub Vec<UrlStore>);
 
 impl UrlIndex {
-    fn load_index(reader: &mut Read) -> Result<UrlStore, Error> {
+    fn load_index(dir: &Path, reader: &mut Read) -> Result<UrlStore, Error> {
         let first_index = reader.read_u64::<LittleEndian>()?;
         let num_entries = reader.read_u64::<LittleEndian>()?;
 
         let store_path_len = reader.read_u16::<LittleEndian>()? as usize;
         let mut store_path_bytes = vec![0; store_path_len];
         reader.read_exact(&mut store_path_bytes)?;
-        let file_path = String::from_utf8(store_path_bytes).unwrap();
+        let file_path = dir.join(String::from_utf8(store_path_bytes).unwrap());
 
         UrlStore::load(file_path, first_index, num_entries)
     }
 
-    pub fn load() -> Result<UrlIndex, StrError> {
-        let url_idx_store_path = "urls.xraystore";
+    pub fn load(dir: &Path) -> Result<UrlIndex, StrError> {
+        let url_idx_store_path = dir.join("urls.xraystore");
         let mut url_idx_store = BufReader::new(
             OpenOptions::new()
                 .read(true)
@@ -132,13 +171,13 @@ impl UrlIndex {
                 .open(&url_idx_store_path)
                 .expect(&format!(
                     "Could not open or create URL storage file {}",
-                    url_idx_store_path,
+                    url_idx_store_path.display(),
                 )),
         );
 
         let mut table_entries = Vec::new();
         loop {
-            match UrlIndex::load_index(&mut url_idx_store) {
+            match UrlIndex::load_index(dir, &mut url_idx_store) {
                 Ok(index) => table_entries.push(index),
                 Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => break,
                 Err(err) => Err(err)?,
@@ -148,6 +187,7 @@ impl UrlIndex {
         Ok(UrlIndex(table_entries))
     }
 
+    /// the URLs of the pages, leaving out ids that no store has
     pub fn get_urls(&self, mut ids: Vec<u64>) -> HashMap<u64, String> {
         ids.sort_unstable();
 
@@ -162,19 +202,18 @@ impl UrlIndex {
             urls.extend(store.get_urls(&elements).unwrap());
         }
 
-        assert_eq!(urls.len(), ids.len());
         urls
     }
 }
 
-fn build_url_jump_table(sorted_urls: &Vec<(&u64, &String)>) -> Vec<u64> {
+fn build_url_jump_table(sorted_urls: &Vec<(&u64, &String)>, jump_stride: u32) -> Vec<u64> {
     let mut jump_table = Vec::new();
     let mut jump_loc = 0u64;
     let mut jump_idx = 0;
 
     for &(_, url) in sorted_urls {
-        // emit a jump table entry for every JUMP_STRIDE urls
-        if jump_idx % JUMP_STRIDE == 0 && jump_idx != 0 {
+        // emit a jump table entry for every `jump_stride` urls
+        if jump_idx % jump_stride == 0 && jump_idx != 0 {
             jump_table.push(jump_loc);
         }
 
@@ -186,40 +225,29 @@ fn build_url_jump_table(sorted_urls: &Vec<(&u64, &String)>) -> Vec<u64> {
     jump_table
 }
 
-pub fn store_urls(urls: &HashMap<u64, String>) -> Result<(), StrError> {
+pub fn store_urls(
+    dir: &Path,
+    jump_stride: u32,
+    urls: &HashMap<u64, String>,
+) -> Result<(), StrError> {
     let mut sortable_urls = urls.iter().collect::<Vec<_>>();
     sortable_urls.par_sort_unstable_by_key(|v| v.0);
     if sortable_urls.is_empty() {
         return Ok(());
     }
 
-    let jump_table = build_url_jump_table(&sortable_urls);
+    let jump_table = build_url_jump_table(&sortable_urls, jump_stride);
 
     let start_idx = *sortable_urls[0].0 as u64;
+    let num_urls = sortable_urls.len() as u64;
     let url_store_loc = &format!("urls_{}.xraystore", start_idx);
-    let mut url_store = BufWriter::new(File::create(url_store_loc)?);
-
-    let mut url_idx_store = BufWriter::new(OpenOptions::new().append(true).open("urls.xraystore")?);
-
-    // write out the starting index for the URLs in this file first
-    url_idx_store.write_u64::<LittleEndian>(start_idx)?;
-
-    // write out how many URLs are in this file
-    url_idx_store.write_u64::<LittleEndian>(
-        sortable_urls.len() as u64,
-    )?;
-
-    // save the file name of this URL store
-    url_idx_store.write_u16::<LittleEndian>(
-        url_store_loc.len() as u16,
-    )?;
-    url_idx_store.write(url_store_loc.as_bytes())?;
+    let mut url_store = BufWriter::new(File::create(dir.join(url_store_loc))?);
 
     // write out the number of entries in the jump table
     url_store.write_u64::<LittleEndian>(jump_table.len() as u64)?;
 
     // write out the stride length of the jump table
-    url_store.write_u32::<LittleEndian>(JUMP_STRIDE)?;
+    url_store.write_u32::<LittleEndian>(jump_stride)?;
 
     // write out the jump table
     for entry in jump_table {
@@ -240,5 +268,27 @@ pub fn store_urls(urls: &HashMap<u64, String>) -> Result<(), StrError> {
         url_store.write(url)?;
     }
 
+    // the store is only added to the index once it is complete, so that the index never refers
+    // to a partially written store
+    url_store.flush()?;
+    let mut url_idx_store = BufWriter::new(
+        OpenOptions::new()
+            .append(true)
+            .open(dir.join("urls.xraystore"))?,
+    );
+
+    // write out the starting index for the URLs in this file first
+    url_idx_store.write_u64::<LittleEndian>(start_idx)?;
+
+    // write out how many URLs are in this file
+    url_idx_store.write_u64::<LittleEndian>(num_urls)?;
+
+    // save the file name of this URL store
+    url_idx_store.write_u16::<LittleEndian>(
+        url_store_loc.len() as u16,
+    )?;
+    url_idx_store.write(url_store_loc.as_bytes())?;
+    url_idx_store.flush()?;
+
     Ok(())
 }